@@ -0,0 +1,27 @@
+/* Click-to-sort for table.sortable, embedded by --offline in place of the CDN script.
+   Sorts by each cell's data-sort attribute or text; numbers compare numerically.
+   Statements end with semicolons because the page is minified onto one line. */
+document.addEventListener("click", function (event) {
+    var th = event.target.closest("table.sortable thead th");
+    if (!th || th.classList.contains("no-sort")) return;
+    var table = th.closest("table");
+    var column = Array.prototype.indexOf.call(th.parentNode.children, th);
+    var ascending = th.getAttribute("aria-sort") === "descending";
+    th.parentNode.querySelectorAll("th").forEach(function (other) { other.removeAttribute("aria-sort"); });
+    th.setAttribute("aria-sort", ascending ? "ascending" : "descending");
+    var value = function (row) {
+        var cell = row.children[column];
+        if (!cell) return "";
+        return cell.hasAttribute("data-sort") ? cell.getAttribute("data-sort") : cell.textContent.trim();
+    };
+    var compare = function (a, b) {
+        var x = value(a), y = value(b);
+        var number = /^-?\d+(\.\d+)?$/;
+        var order = number.test(x) && number.test(y) ? parseFloat(x) - parseFloat(y) : x.localeCompare(y);
+        return ascending ? order : -order;
+    };
+    Array.prototype.forEach.call(table.tBodies, function (body) {
+        var rows = Array.prototype.slice.call(body.rows).sort(compare);
+        rows.forEach(function (row) { body.appendChild(row); });
+    });
+});
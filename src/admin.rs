@@ -0,0 +1,234 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use crate::parser::PlayerStats;
+use crate::publish;
+use crate::writer::write_userstats;
+
+/// How recently `userstats.dat` may have been modified before we assume the
+/// server is still writing to it.
+pub const BUSY_WINDOW: Duration = Duration::from_secs(60);
+
+/// Zeroes every counter of the player with `key`, keeping name and account.
+/// Returns `false` if no such player exists.
+pub fn reset(players: &mut [PlayerStats], key: (u8, i64)) -> bool {
+    match players.iter_mut().find(|p| p.key() == key) {
+        Some(p) => {
+            p.score = 0;
+            p.kills = 0;
+            p.deaths = 0;
+            p.assists = 0;
+            p.mvps = 0;
+            p.time = 0;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes the player with `key`. Returns `false` if no such player exists.
+pub fn remove(players: &mut Vec<PlayerStats>, key: (u8, i64)) -> bool {
+    let len = players.len();
+    players.retain(|p| p.key() != key);
+    players.len() != len
+}
+
+/// Changes the name of the player with `key`. Returns `false` if no such player exists.
+pub fn rename(players: &mut [PlayerStats], key: (u8, i64), name: &str) -> bool {
+    match players.iter_mut().find(|p| p.key() == key) {
+        Some(p) => {
+            p.name = name.to_string();
+            p.raw_name = name.as_bytes().to_vec();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Adds every counter of the player `from` to the player `into` and removes `from`.
+/// Returns `false` if either player is missing or both keys are the same, and
+/// an error naming the counter, with `players` unchanged, if a sum would overflow.
+pub fn merge(players: &mut Vec<PlayerStats>, from: (u8, i64), into: (u8, i64)) -> Result<bool, String> {
+    if from == into {
+        return Ok(false);
+    }
+    let Some(source) = players.iter().position(|p| p.key() == from) else {
+        return Ok(false);
+    };
+    let Some(target) = players.iter().position(|p| p.key() == into) else {
+        return Ok(false);
+    };
+
+    let (source, target) = (&players[source], &players[target]);
+    let add = |name: &str, a: i32, b: i32| a.checked_add(b).ok_or_else(|| format!("the merged {} would overflow", name));
+    let merged = PlayerStats {
+        score: add("score", target.score, source.score)?,
+        kills: add("kills", target.kills, source.kills)?,
+        deaths: add("deaths", target.deaths, source.deaths)?,
+        assists: add("assists", target.assists, source.assists)?,
+        mvps: add("MVPs", target.mvps, source.mvps)?,
+        time: add("time", target.time, source.time)?,
+        ..target.clone()
+    };
+
+    players.retain(|p| p.key() != from);
+    if let Some(target) = players.iter_mut().find(|p| p.key() == into) {
+        *target = merged;
+    }
+    Ok(true)
+}
+
+/// Explains why `path` should not be edited right now, or returns `None` if it looks idle.
+///
+/// CS2D keeps stats in memory and rewrites the whole file periodically, so
+/// edits made while the server runs are lost or, worse, interleaved with its writes.
+/// Recent writes made by [`save_with_backup`] itself do not count.
+pub fn busy_reason(path: &Path) -> Option<String> {
+    if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        if age < BUSY_WINDOW && !saved_by_admin(path, modified) {
+            return Some(format!("'{}' was modified {} s ago", path.display(), age.as_secs()));
+        }
+    }
+    let folder = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    running_server(folder).map(|pid| format!("a CS2D server process is running in this server's folder (pid {})", pid))
+}
+
+/// Whether the last write to `path` was [`save_with_backup`], which gives its
+/// backup the same modification time as the file it wrote.
+fn saved_by_admin(path: &Path, modified: SystemTime) -> bool {
+    publish::backups(path)
+        .ok()
+        .and_then(|backups| backups.last().and_then(|b| fs::metadata(b).and_then(|m| m.modified()).ok()))
+        .is_some_and(|backup| backup == modified)
+}
+
+/// A CS2D server process running in the server folder that holds the stats
+/// `folder`, `<server>/sys/stats`. Processes whose folder cannot be read count too.
+#[cfg(target_os = "linux")]
+fn running_server(folder: &Path) -> Option<u32> {
+    let folder = fs::canonicalize(folder).ok()?;
+    let server = folder.parent()?.parent()?;
+    fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+        let pid = entry.file_name().to_str()?.parse().ok()?;
+        let comm = fs::read_to_string(entry.path().join("comm")).ok()?;
+        if !matches!(comm.trim(), "cs2d_dedicated" | "cs2d") {
+            return None;
+        }
+        fs::read_link(entry.path().join("cwd")).map_or(true, |cwd| cwd == server).then_some(pid)
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn running_server(_folder: &Path) -> Option<u32> {
+    None
+}
+
+/// Copies `path` to a timestamped backup next to it, then replaces it with
+/// `players` through a temporary file so a failed write never leaves it half-written.
+/// Returns the path of the backup.
+pub fn save_with_backup(path: &Path, players: &[PlayerStats]) -> io::Result<PathBuf> {
+    let backup = publish::backup(path, None)?;
+    publish::replace(path, |writer| write_userstats(writer, players))?;
+    // Lets `busy_reason` tell this write from one made by the server.
+    let modified = fs::metadata(path)?.modified()?;
+    File::options().write(true).open(&backup)?.set_modified(modified)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+    use crate::parser::read_userstats;
+    use crate::writer::save_userstats;
+
+    fn player(userid: i64, [score, kills, deaths, assists, mvps, time]: [i32; 6]) -> PlayerStats {
+        let name = userid.to_string();
+        PlayerStats { raw_name: name.as_bytes().to_vec(), name, usertype: 0, userid, score, kills, deaths, assists, mvps, time }
+    }
+
+    fn players() -> Vec<PlayerStats> {
+        vec![player(1, [10, 5, 3, 2, 1, 600]), player(2, [4, 2, 1, 0, 0, 60])]
+    }
+
+    #[test]
+    fn reset_zeroes_counters_only() {
+        let mut players = players();
+        assert!(reset(&mut players, (0, 1)));
+        assert_eq!(players[0], player(1, [0; 6]));
+        assert_eq!(players[1], self::players()[1]);
+        assert!(!reset(&mut players, (0, 3)));
+        assert!(!reset(&mut players, (1, 1)));
+    }
+
+    #[test]
+    fn remove_drops_the_player() {
+        let mut players = players();
+        assert!(remove(&mut players, (0, 1)));
+        assert_eq!(players, [player(2, [4, 2, 1, 0, 0, 60])]);
+        assert!(!remove(&mut players, (0, 1)));
+        assert_eq!(players.len(), 1);
+    }
+
+    #[test]
+    fn rename_updates_the_stored_name() {
+        let mut players = players();
+        players[0].raw_name = b"old \xe9".to_vec();
+        assert!(rename(&mut players, (0, 1), "Zoë"));
+        assert_eq!(players[0].name, "Zoë");
+        assert_eq!(players[0].raw_name, "Zoë".as_bytes());
+        assert!(!rename(&mut players, (0, 3), "nobody"));
+
+        let mut written = Vec::new();
+        write_userstats(&mut written, &players).unwrap();
+        assert_eq!(read_userstats(written.as_slice()).unwrap()[0].name, "Zoë");
+    }
+
+    #[test]
+    fn merge_adds_counters_into_the_target() {
+        let mut players = players();
+        assert_eq!(merge(&mut players, (0, 2), (0, 1)), Ok(true));
+        assert_eq!(players, [player(1, [14, 7, 4, 2, 1, 660])]);
+    }
+
+    #[test]
+    fn merge_without_two_players_changes_nothing() {
+        let mut players = players();
+        assert_eq!(merge(&mut players, (0, 3), (0, 1)), Ok(false));
+        assert_eq!(merge(&mut players, (0, 1), (0, 3)), Ok(false));
+        assert_eq!(merge(&mut players, (0, 1), (0, 1)), Ok(false));
+        assert_eq!(players, self::players());
+    }
+
+    #[test]
+    fn merge_refuses_to_overflow() {
+        let mut players = players();
+        players[1].time = i32::MAX;
+        let before = players.clone();
+        assert_eq!(merge(&mut players, (0, 2), (0, 1)), Err("the merged time would overflow".to_string()));
+        assert_eq!(players, before);
+    }
+
+    #[test]
+    fn own_saves_do_not_look_busy() {
+        let dir = env::temp_dir().join(format!("cs2d-admin-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("userstats.dat");
+        save_userstats(&path, &players()).unwrap();
+        assert!(busy_reason(&path).is_some());
+
+        let mut players = players();
+        remove(&mut players, (0, 2));
+        let backup = save_with_backup(&path, &players).unwrap();
+        assert_eq!(busy_reason(&path), None);
+        assert_eq!(read_userstats(File::open(&path).unwrap()).unwrap(), players);
+        assert_eq!(read_userstats(File::open(&backup).unwrap()).unwrap(), self::players());
+
+        // A write by the server afterwards counts again.
+        save_userstats(&path, &players).unwrap();
+        assert!(busy_reason(&path).is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use chrono::{Local, NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{self, Path, PathBuf};
+use crate::parser::{PlayerStats, TrafficStats};
+use crate::publish;
+
+const ID_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// The state of the stats files at one point in time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: NaiveDateTime,
+    pub players: Vec<PlayerStats>,
+    /// Traffic records that were new since the previous snapshot, plus those of
+    /// older snapshots deleted by [`Archive::prune`].
+    pub traffic: Vec<TrafficStats>,
+    /// Start of the newest traffic hour known when the snapshot was taken.
+    pub traffic_until: Option<NaiveDateTime>,
+}
+
+/// A directory of timestamped JSON snapshots, one file per run.
+///
+/// Snapshot ids are the file names without extension and sort chronologically.
+pub struct Archive {
+    pub dir: PathBuf,
+    /// Keep at most this many snapshots.
+    pub keep: Option<usize>,
+    /// Delete snapshots older than this.
+    pub max_age: Option<TimeDelta>,
+}
+
+impl Archive {
+    /// Opens the archive in `dir`, creating the directory if needed. No retention limits are set.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Archive { dir, keep: None, max_age: None })
+    }
+
+    /// Checks that `dir` is a folder, or could be created as one by [`Archive::open`],
+    /// without creating anything.
+    pub fn check<P: AsRef<Path>>(dir: P) -> io::Result<()> {
+        let dir = path::absolute(dir)?;
+        let existing = dir.ancestors().find(|path| path.exists()).unwrap_or(&dir);
+        if existing.is_dir() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotADirectory, format!("'{}' is not a folder", existing.display())))
+        }
+    }
+
+    /// Returns the ids of all snapshots, oldest first.
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        let mut ids: Vec<String> = fs::read_dir(&self.dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let id = name.strip_suffix(".json")?;
+                NaiveDateTime::parse_from_str(id, ID_FORMAT).ok()?;
+                Some(id.to_string())
+            })
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Resolves `latest` to the id of the newest snapshot; other ids are returned as is.
+    pub fn resolve(&self, id: &str) -> io::Result<String> {
+        match id {
+            "latest" => self
+                .list()?
+                .pop()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "The archive is empty")),
+            _ => Ok(id.to_string()),
+        }
+    }
+
+    /// Loads the snapshot with `id`; `latest` names the newest one.
+    pub fn load(&self, id: &str) -> io::Result<Snapshot> {
+        let file = File::open(self.dir.join(format!("{}.json", self.resolve(id)?)))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Returns every traffic record stored up to and including the snapshot with `id`.
+    /// Snapshots that cannot be read are skipped.
+    pub fn traffic_until(&self, id: &str) -> io::Result<Vec<TrafficStats>> {
+        let id = self.resolve(id)?;
+        let mut traffic = Vec::new();
+        for snapshot_id in self.list()?.into_iter().take_while(|s| *s <= id) {
+            if let Ok(snapshot) = self.load(&snapshot_id) {
+                traffic.extend(snapshot.traffic);
+            }
+        }
+        Ok(traffic)
+    }
+
+    /// Stores a snapshot of `players` plus the `traffic` records that are newer
+    /// than the newest readable snapshot, then applies the retention limits.
+    /// Returns the id of the new snapshot.
+    pub fn record(&self, players: &[PlayerStats], traffic: &[TrafficStats]) -> io::Result<String> {
+        let previous = self.list()?.iter().rev().find_map(|id| self.load(id).ok()).and_then(|s| s.traffic_until);
+        let traffic: Vec<TrafficStats> = traffic
+            .iter()
+            .filter(|t| match (t.datetime(), previous) {
+                (Some(time), Some(previous)) => time > previous,
+                _ => true,
+            })
+            .cloned()
+            .collect();
+
+        let taken_at = Local::now().naive_local();
+        let snapshot = Snapshot {
+            taken_at,
+            players: players.to_vec(),
+            traffic_until: traffic.iter().filter_map(|t| t.datetime()).max().or(previous),
+            traffic,
+        };
+
+        let id = taken_at.format(ID_FORMAT).to_string();
+        self.save(&id, &snapshot)?;
+
+        self.prune()?;
+        Ok(id)
+    }
+
+    /// Deletes snapshots beyond the retention limits. Returns how many were removed.
+    ///
+    /// Each snapshot holds the only copy of its traffic records, so those of the
+    /// deleted snapshots are moved into the oldest readable snapshot that is kept.
+    pub fn prune(&self) -> io::Result<usize> {
+        let ids = self.list()?;
+        let over_limit = self.keep.map_or(0, |keep| ids.len().saturating_sub(keep));
+        let cutoff = self.max_age.map(|age| Local::now().naive_local() - age);
+
+        let (expired, kept): (Vec<_>, Vec<_>) = ids.iter().enumerate().partition(|(i, id)| {
+            let too_old = match (cutoff, NaiveDateTime::parse_from_str(id, ID_FORMAT)) {
+                (Some(cutoff), Ok(taken_at)) => taken_at < cutoff,
+                _ => false,
+            };
+            *i < over_limit || too_old
+        });
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        let traffic: Vec<TrafficStats> =
+            expired.iter().filter_map(|(_, id)| self.load(id).ok()).flat_map(|snapshot| snapshot.traffic).collect();
+        if !traffic.is_empty()
+            && let Some((id, mut oldest)) = kept.iter().find_map(|(_, id)| Some((*id, self.load(id).ok()?)))
+        {
+            oldest.traffic.splice(0..0, traffic);
+            self.save(id, &oldest)?;
+        }
+
+        for (_, id) in &expired {
+            fs::remove_file(self.dir.join(format!("{}.json", id)))?;
+        }
+        Ok(expired.len())
+    }
+
+    fn save(&self, id: &str, snapshot: &Snapshot) -> io::Result<()> {
+        publish::replace(&self.dir.join(format!("{}.json", id)), |writer| Ok(serde_json::to_writer(writer, snapshot)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process, thread, time::Duration};
+
+    fn archive(name: &str) -> Archive {
+        let dir = env::temp_dir().join(format!("cs2d-archive-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Archive::open(dir).unwrap()
+    }
+
+    fn hours(range: std::ops::Range<u8>) -> Vec<TrafficStats> {
+        range
+            .map(|hour| TrafficStats { day: 1, month: 4, year: 2025, hour, uploaded_bytes: 10, downloaded_bytes: 20, players: 1 })
+            .collect()
+    }
+
+    /// Records a snapshot in a new millisecond, so ids never collide.
+    fn record(archive: &Archive, traffic: &[TrafficStats]) -> String {
+        thread::sleep(Duration::from_millis(2));
+        archive.record(&[], traffic).unwrap()
+    }
+
+    #[test]
+    fn record_stores_only_new_traffic() {
+        let archive = archive("record");
+        let first = record(&archive, &hours(0..2));
+        let second = record(&archive, &hours(0..3));
+
+        assert_eq!(archive.load(&first).unwrap().traffic, hours(0..2));
+        assert_eq!(archive.load(&second).unwrap().traffic, hours(2..3));
+        assert_eq!(archive.traffic_until(&first).unwrap(), hours(0..2));
+        assert_eq!(archive.traffic_until("latest").unwrap(), hours(0..3));
+        fs::remove_dir_all(&archive.dir).unwrap();
+    }
+
+    #[test]
+    fn pruning_by_count_keeps_the_traffic() {
+        let mut archive = archive("keep");
+        archive.keep = Some(2);
+        record(&archive, &hours(0..2));
+        record(&archive, &hours(0..4));
+        let third = record(&archive, &hours(0..5));
+
+        let ids = archive.list().unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(archive.load(&ids[0]).unwrap().traffic, hours(0..4));
+        assert_eq!(archive.traffic_until(&third).unwrap(), hours(0..5));
+        fs::remove_dir_all(&archive.dir).unwrap();
+    }
+
+    #[test]
+    fn pruning_by_age_keeps_the_traffic() {
+        let mut archive = archive("age");
+        let old = record(&archive, &hours(0..3));
+        fs::rename(archive.dir.join(format!("{}.json", old)), archive.dir.join("20200101-000000.000.json")).unwrap();
+        record(&archive, &hours(0..4));
+
+        archive.max_age = Some(TimeDelta::days(1));
+        assert_eq!(archive.prune().unwrap(), 1);
+        assert_eq!(archive.list().unwrap().len(), 1);
+        assert_eq!(archive.traffic_until("latest").unwrap(), hours(0..4));
+        assert_eq!(archive.prune().unwrap(), 0);
+        fs::remove_dir_all(&archive.dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_snapshots_are_skipped() {
+        let archive = archive("unreadable");
+        let first = record(&archive, &hours(0..2));
+        fs::write(archive.dir.join("29990101-000000.000.json"), "{\"taken_at\":").unwrap();
+
+        let second = record(&archive, &hours(0..3));
+        assert_eq!(archive.load(&second).unwrap().traffic, hours(2..3));
+        assert_eq!(archive.traffic_until("latest").unwrap(), hours(0..3));
+        assert!(archive.load(&first).is_ok());
+        fs::remove_dir_all(&archive.dir).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{Datelike, NaiveDateTime};
+use std::fmt::Write;
+use crate::output::format_bytes;
+use crate::parser::TrafficStats;
+use crate::traffic::TrafficSummary;
+
+const WIDTH: f64 = 900.0;
+const HEIGHT: f64 = 220.0;
+/// Room for the y axis labels on the left, and for the legend and x axis labels.
+const LEFT: f64 = 70.0;
+const TOP: f64 = 20.0;
+const BOTTOM: f64 = 24.0;
+const UPLOAD_COLOR: &str = "#3c9d40";
+const DOWNLOAD_COLOR: &str = "#1095c1";
+/// At most this many labels are drawn along the x axis.
+const X_LABELS: usize = 10;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Grouped bars of the bytes uploaded and downloaded on each recorded day.
+pub fn bandwidth_per_day(traffic: &[TrafficStats]) -> String {
+    let days = TrafficSummary::new(traffic).days;
+    let max = days.iter().map(|d| d.uploaded.max(d.downloaded)).max().unwrap_or(0).max(1) as f64;
+    let slot = plot_width() / days.len().max(1) as f64;
+    let bar = slot * 0.4;
+
+    let mut svg = open(HEIGHT, "Bandwidth per day");
+    grid(&mut svg, max, |v| format_bytes(v as u64));
+    legend(&mut svg, &[("Upload", UPLOAD_COLOR), ("Download", DOWNLOAD_COLOR)]);
+
+    for (i, day) in days.iter().enumerate() {
+        let x = LEFT + i as f64 * slot + slot * 0.1;
+        for (j, (name, bytes, color)) in
+            [("upload", day.uploaded, UPLOAD_COLOR), ("download", day.downloaded, DOWNLOAD_COLOR)].into_iter().enumerate()
+        {
+            let height = bytes as f64 / max * plot_height();
+            let _ = write!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"><title>{} {}: {}</title></rect>"#,
+                x + j as f64 * bar,
+                TOP + plot_height() - height,
+                bar,
+                height,
+                color,
+                day.label,
+                name,
+                format_bytes(bytes)
+            );
+        }
+    }
+
+    let labels = days.iter().enumerate().map(|(i, d)| (LEFT + (i as f64 + 0.5) * slot, d.label[5..].to_string()));
+    x_labels(&mut svg, labels.collect());
+    svg.push_str("</svg>");
+    svg
+}
+
+/// A line of the concurrent players in every recorded hour, on a real time axis.
+pub fn players_over_time(traffic: &[TrafficStats]) -> String {
+    let mut points: Vec<(NaiveDateTime, u8)> =
+        traffic.iter().filter_map(|t| Some((t.datetime()?, t.players))).collect();
+    points.sort_by_key(|(time, _)| *time);
+
+    let max = points.iter().map(|(_, players)| *players).max().unwrap_or(0).max(1) as f64;
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => (NaiveDateTime::default(), NaiveDateTime::default()),
+    };
+    let span = (last - first).num_seconds().max(1) as f64;
+    let x = |time: NaiveDateTime| LEFT + (time - first).num_seconds() as f64 / span * plot_width();
+
+    let mut svg = open(HEIGHT, "Concurrent players over time");
+    grid(&mut svg, max, |v| format!("{:.0}", v));
+
+    let line: Vec<String> = points
+        .iter()
+        .map(|(time, players)| format!("{:.1},{:.1}", x(*time), TOP + plot_height() - *players as f64 / max * plot_height()))
+        .collect();
+    let _ = write!(
+        svg,
+        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="1.5" stroke-linejoin="round"/>"#,
+        line.join(" "),
+        DOWNLOAD_COLOR
+    );
+
+    let labels = (0..X_LABELS.min(points.len()))
+        .map(|i| {
+            let time = first + (last - first) * i as i32 / (X_LABELS.min(points.len()) - 1).max(1) as i32;
+            (x(time), time.format("%m-%d").to_string())
+        })
+        .collect();
+    x_labels(&mut svg, labels);
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Average concurrent players for each weekday and hour of the day.
+pub fn activity_heatmap(traffic: &[TrafficStats]) -> String {
+    let mut cells = [[(0u64, 0u64); 24]; 7];
+    for t in traffic {
+        if let Some(time) = t.datetime() {
+            let cell = &mut cells[time.weekday().num_days_from_monday() as usize][t.hour as usize];
+            cell.0 += t.players as u64;
+            cell.1 += 1;
+        }
+    }
+    let average = |(players, hours): (u64, u64)| if hours > 0 { players as f64 / hours as f64 } else { 0.0 };
+    let max = cells.iter().flatten().map(|c| average(*c)).fold(0.0, f64::max).max(1.0);
+
+    let cell_width = plot_width() / 24.0;
+    let cell_height = 22.0;
+    let mut svg = open(TOP + 7.0 * cell_height + BOTTOM, "Activity by weekday and hour");
+
+    for (day, hours) in cells.iter().enumerate() {
+        let y = TOP + day as f64 * cell_height;
+        let _ = write!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end" dominant-baseline="middle">{}</text>"#,
+            LEFT - 8.0,
+            y + cell_height / 2.0,
+            WEEKDAYS[day]
+        );
+        for (hour, cell) in hours.iter().enumerate() {
+            let x = LEFT + hour as f64 * cell_width;
+            let value = average(*cell);
+            let fill = if cell.1 > 0 {
+                format!(r#"fill="{}" fill-opacity="{:.2}""#, DOWNLOAD_COLOR, (value / max).max(0.05))
+            } else {
+                r#"fill="none""#.to_string()
+            };
+            let _ = write!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" {} stroke="currentColor" stroke-opacity="0.1"><title>{} {:02}:00: {:.1} players ({} h)</title></rect>"#,
+                x,
+                y,
+                cell_width,
+                cell_height,
+                fill,
+                WEEKDAYS[day],
+                hour,
+                value,
+                cell.1
+            );
+        }
+    }
+
+    let labels = (0..24)
+        .step_by(3)
+        .map(|hour| (LEFT + (hour as f64 + 0.5) * cell_width, format!("{:02}", hour)))
+        .collect();
+    x_labels_at(&mut svg, labels, TOP + 7.0 * cell_height);
+    svg.push_str("</svg>");
+    svg
+}
+
+fn plot_width() -> f64 {
+    WIDTH - LEFT - 10.0
+}
+
+fn plot_height() -> f64 {
+    HEIGHT - TOP - BOTTOM
+}
+
+fn open(height: f64, label: &str) -> String {
+    format!(
+        r#"<svg viewBox="0 0 {} {}" width="100%" role="img" aria-label="{}" font-size="11" fill="currentColor" xmlns="http://www.w3.org/2000/svg">"#,
+        WIDTH, height, label
+    )
+}
+
+/// Horizontal grid lines at zero, half and the full `max`, labelled with `label`.
+fn grid(svg: &mut String, max: f64, label: impl Fn(f64) -> String) {
+    for step in 0..=2 {
+        let value = max * step as f64 / 2.0;
+        let y = TOP + plot_height() - plot_height() * step as f64 / 2.0;
+        let _ = write!(
+            svg,
+            r#"<line x1="{}" y1="{:.1}" x2="{}" y2="{:.1}" stroke="currentColor" stroke-opacity="0.2"/><text x="{}" y="{:.1}" text-anchor="end" dominant-baseline="middle">{}</text>"#,
+            LEFT,
+            y,
+            LEFT + plot_width(),
+            y,
+            LEFT - 8.0,
+            y,
+            label(value)
+        );
+    }
+}
+
+fn legend(svg: &mut String, entries: &[(&str, &str)]) {
+    let mut x = LEFT + plot_width();
+    for (name, color) in entries.iter().rev() {
+        x -= 8.0 * name.len() as f64 + 24.0;
+        let _ = write!(
+            svg,
+            r#"<rect x="{:.1}" y="4" width="10" height="10" fill="{}"/><text x="{:.1}" y="13">{}</text>"#,
+            x,
+            color,
+            x + 14.0,
+            name
+        );
+    }
+}
+
+/// Draws at most [`X_LABELS`] of `labels` evenly spread below the plot area.
+fn x_labels(svg: &mut String, labels: Vec<(f64, String)>) {
+    let step = labels.len().div_ceil(X_LABELS).max(1);
+    x_labels_at(svg, labels.into_iter().step_by(step).collect(), TOP + plot_height());
+}
+
+fn x_labels_at(svg: &mut String, labels: Vec<(f64, String)>, y: f64) {
+    for (x, label) in labels {
+        let _ = write!(svg, r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#, x, y + 16.0, label);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Options of a profile by name, each with one or more values.
+pub type Options = BTreeMap<String, Vec<String>>;
+
+/// A configuration file of named server profiles.
+///
+/// Every profile is a set of command-line options keyed by their long name
+/// (`title`, `min-time`, `output`, ...) or `folder`; `_` may stand for `-`.
+/// Options at the top level apply to every profile that does not set them:
+///
+/// ```toml
+/// limit = 50
+///
+/// [profiles.dm]
+/// folder = "/home/cs2d/dm/sys/stats"
+/// output = ["/var/www/dm.html", "/var/www/dm.json"]
+/// title = "Deathmatch"
+/// ```
+pub struct Config {
+    pub profiles: BTreeMap<String, Options>,
+}
+
+impl Config {
+    /// Reads the configuration file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut table: Table =
+            toml::from_str(&fs::read_to_string(path)?).map_err(|e| invalid(e.to_string().trim_end().to_string()))?;
+
+        let profiles = match table.remove("profiles") {
+            Some(Value::Table(profiles)) if !profiles.is_empty() => profiles,
+            Some(Value::Table(_)) | None => return Err(invalid("no [profiles.<name>] sections".to_string())),
+            Some(_) => return Err(invalid("'profiles' must be a table of profiles".to_string())),
+        };
+        let defaults = options(&table).map_err(invalid)?;
+
+        let profiles = profiles
+            .into_iter()
+            .map(|(name, profile)| {
+                let Value::Table(profile) = profile else {
+                    return Err(invalid(format!("profile '{}' must be a table", name)));
+                };
+                let mut merged = defaults.clone();
+                merged.extend(options(&profile).map_err(|e| invalid(format!("profile '{}': {}", name, e)))?);
+                Ok((name, merged))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Config { profiles })
+    }
+}
+
+fn options(table: &Table) -> Result<Options, String> {
+    table
+        .iter()
+        .map(|(key, value)| {
+            let values = match value {
+                Value::Array(items) => items.iter().map(scalar).collect(),
+                value => scalar(value).map(|value| vec![value]),
+            };
+            let values = values.ok_or_else(|| format!("option '{}' must be a string, number, boolean or array of them", key))?;
+            Ok((key.replace('_', "-"), values))
+        })
+        .collect()
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Integer(n) => Some(n.to_string()),
+        Value::Float(n) => Some(n.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
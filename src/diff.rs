@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use crate::parser::PlayerStats;
+
+/// Computes per-player activity between two `userstats.dat` snapshots.
+///
+/// Players are matched by `usertype` and `userid`, and every counter of `old`
+/// is subtracted from `new`, saturating at the `i32` limits. Players that are new in `new`, or whose playtime
+/// went down because their stats were reset in between, count with their full
+/// `new` totals. Players without any playtime in the window are left out.
+pub fn diff_players(old: &[PlayerStats], new: &[PlayerStats]) -> Vec<PlayerStats> {
+    let old: HashMap<(u8, i64), &PlayerStats> = old.iter().map(|p| (p.key(), p)).collect();
+
+    new.iter()
+        .filter_map(|p| {
+            let delta = match old.get(&p.key()) {
+                Some(before) if before.time <= p.time => PlayerStats {
+                    score: p.score.saturating_sub(before.score),
+                    kills: p.kills.saturating_sub(before.kills),
+                    deaths: p.deaths.saturating_sub(before.deaths),
+                    assists: p.assists.saturating_sub(before.assists),
+                    mvps: p.mvps.saturating_sub(before.mvps),
+                    time: p.time.saturating_sub(before.time),
+                    ..p.clone()
+                },
+                _ => p.clone(),
+            };
+            (delta.time > 0).then_some(delta)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(userid: i64, [score, kills, deaths, assists, mvps, time]: [i32; 6]) -> PlayerStats {
+        PlayerStats { name: userid.to_string(), raw_name: Vec::new(), usertype: 0, userid, score, kills, deaths, assists, mvps, time }
+    }
+
+    #[test]
+    fn counters_are_subtracted() {
+        let old = [player(1, [10, 5, 3, 2, 1, 600])];
+        let new = [player(1, [15, 9, 4, 2, 2, 900])];
+        assert_eq!(diff_players(&old, &new), [player(1, [5, 4, 1, 0, 1, 300])]);
+    }
+
+    #[test]
+    fn reset_players_count_with_their_new_totals() {
+        let old = [player(1, [100, 50, 40, 10, 5, 9000])];
+        let new = [player(1, [3, 2, 1, 0, 0, 120])];
+        assert_eq!(diff_players(&old, &new), new);
+    }
+
+    #[test]
+    fn new_players_count_with_their_totals() {
+        let old = [player(1, [10, 5, 3, 2, 1, 600])];
+        let new = [player(1, [12, 6, 3, 2, 1, 700]), player(2, [4, 2, 1, 0, 0, 60])];
+        assert_eq!(diff_players(&old, &new), [player(1, [2, 1, 0, 0, 0, 100]), player(2, [4, 2, 1, 0, 0, 60])]);
+    }
+
+    #[test]
+    fn players_without_playtime_are_dropped() {
+        let old = [player(1, [10, 5, 3, 2, 1, 600]), player(2, [0, 0, 0, 0, 0, 0])];
+        let new = [player(1, [10, 5, 3, 2, 1, 600]), player(2, [0, 0, 0, 0, 0, 0]), player(3, [0, 0, 0, 0, 0, 0])];
+        assert!(diff_players(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn differences_saturate() {
+        let old = [player(1, [i32::MIN, 0, 0, 0, 0, 0])];
+        let new = [player(1, [i32::MAX, 0, 0, 0, 0, 60])];
+        assert_eq!(diff_players(&old, &new)[0].score, i32::MAX);
+    }
+}
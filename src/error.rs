@@ -0,0 +1,78 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Errors returned while parsing `userstats.dat` or `stats.dat`.
+///
+/// Offsets are byte positions from the start of the file and record indices
+/// are zero-based, counting from the first record after the header line.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The underlying reader failed.
+    Io(io::Error),
+    /// The first line of the file is not the expected format header.
+    BadHeader {
+        expected: &'static str,
+        found: String,
+    },
+    /// The file ended in the middle of a record.
+    TruncatedRecord {
+        offset: u64,
+        record: usize,
+        field: &'static str,
+    },
+    /// A field holds a value the format does not allow.
+    InvalidField {
+        offset: u64,
+        record: usize,
+        field: &'static str,
+        value: i64,
+    },
+    /// Unexpected bytes follow the end-of-records marker.
+    TrailingGarbage {
+        offset: u64,
+        record: usize,
+        len: u64,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "{}", e),
+            ParseError::BadHeader { expected, found } => {
+                write!(f, "invalid header: expected '{}', found '{}'", expected, found)
+            }
+            ParseError::TruncatedRecord { offset, record, field } => write!(
+                f,
+                "truncated record {} at byte {}: file ends inside field '{}'",
+                record, offset, field
+            ),
+            ParseError::InvalidField { offset, record, field, value } => write!(
+                f,
+                "invalid value {} for field '{}' in record {} at byte {}",
+                value, field, record, offset
+            ),
+            ParseError::TrailingGarbage { offset, record, len } => write!(
+                f,
+                "{} unexpected bytes at byte {} after the end of records (record {})",
+                len, offset, record
+            ),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
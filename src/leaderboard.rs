@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+use crate::parser::PlayerStats;
+use crate::publish;
+use crate::ranking::SortSpec;
+
+/// Sorts players best first according to `sort`.
+pub fn sort_players(players: &mut Vec<PlayerStats>, sort: &SortSpec) {
+    sort.sort(players);
+}
+
+/// Drops ineligible players and sorts the rest best first.
+/// Returns how many players the filter excluded.
+pub fn rank(players: &mut Vec<PlayerStats>, filter: &Filter, sort: &SortSpec) -> usize {
+    let excluded = filter.apply(players);
+    sort_players(players, sort);
+    excluded
+}
+
+/// Eligibility rules a player must pass to appear on the leaderboard.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Minimum playtime in seconds.
+    pub min_time: i32,
+    pub min_kills: i32,
+    /// Only keep players of this usertype (`0` = USGN, `1` = Steam).
+    pub only: Option<u8>,
+    /// Keep only players whose name matches.
+    pub include: Option<Regex>,
+    /// Drop players whose name matches.
+    pub exclude: Option<Regex>,
+    /// Ignored accounts; a `None` usertype matches the userid of either type.
+    pub ignored: HashSet<(Option<u8>, i64)>,
+}
+
+impl Filter {
+    pub fn matches(&self, p: &PlayerStats) -> bool {
+        p.time >= self.min_time
+            && p.kills >= self.min_kills
+            && self.only.is_none_or(|usertype| p.usertype == usertype)
+            && self.include.as_ref().is_none_or(|re| re.is_match(&p.name))
+            && !self.exclude.as_ref().is_some_and(|re| re.is_match(&p.name))
+            && !self.ignored.contains(&(Some(p.usertype), p.userid))
+            && !self.ignored.contains(&(None, p.userid))
+    }
+
+    /// Removes ineligible players and returns how many were removed.
+    pub fn apply(&self, players: &mut Vec<PlayerStats>) -> usize {
+        let len = players.len();
+        players.retain(|p| self.matches(p));
+        len - players.len()
+    }
+}
+
+/// Parses a duration such as `90`, `30m`, `2h` or `1d` into seconds.
+pub fn parse_duration(s: &str) -> Result<i32, String> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err("expected seconds or a number followed by s, m, h or d".to_string()),
+    };
+    number
+        .parse::<i32>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("invalid duration '{}'", s))
+}
+
+/// Reads an ignore list: one account per line as `userid`, `usgn:userid` or
+/// `steam:userid`. Blank lines and lines starting with `#` are skipped.
+pub fn load_ignore_list<P: AsRef<Path>>(path: P) -> io::Result<HashSet<(Option<u8>, i64)>> {
+    let mut ignored = HashSet::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (usertype, userid) = match line.split_once(':') {
+            Some((kind, id)) if kind.eq_ignore_ascii_case("usgn") => (Some(0), id),
+            Some((kind, id)) if kind.eq_ignore_ascii_case("steam") => (Some(1), id),
+            Some(_) => (None, ""),
+            None => (None, line),
+        };
+        let userid = userid.trim().parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid entry '{}' on line {}", line, i + 1))
+        })?;
+        ignored.insert((usertype, userid));
+    }
+    Ok(ignored)
+}
+
+/// A player's change in rank since the previous leaderboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    New,
+    Up(usize),
+    Down(usize),
+    Same,
+}
+
+impl Movement {
+    pub fn new(rank: usize, previous_rank: Option<usize>) -> Self {
+        match previous_rank {
+            None => Movement::New,
+            Some(previous) if previous > rank => Movement::Up(previous - rank),
+            Some(previous) if previous < rank => Movement::Down(rank - previous),
+            Some(_) => Movement::Same,
+        }
+    }
+
+    /// Positions gained (positive) or lost (negative), `None` for new players.
+    pub fn delta(self) -> Option<i64> {
+        match self {
+            Movement::New => None,
+            Movement::Up(n) => Some(n as i64),
+            Movement::Down(n) => Some(-(n as i64)),
+            Movement::Same => Some(0),
+        }
+    }
+}
+
+impl fmt::Display for Movement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Movement::New => write!(f, "NEW"),
+            Movement::Up(n) => write!(f, "▲{}", n),
+            Movement::Down(n) => write!(f, "▼{}", n),
+            Movement::Same => write!(f, "–"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RankEntry {
+    usertype: u8,
+    userid: i64,
+    rank: usize,
+}
+
+/// Loads the ranks saved by [`save_ranks`], keyed by `(usertype, userid)`.
+/// A missing file yields no ranks, so every player shows up as new.
+pub fn load_ranks<P: AsRef<Path>>(path: P) -> io::Result<HashMap<(u8, i64), usize>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let entries: Vec<RankEntry> = serde_json::from_reader(BufReader::new(file))?;
+    Ok(entries.into_iter().map(|e| ((e.usertype, e.userid), e.rank)).collect())
+}
+
+/// Saves the rank of every player in the already sorted `players` for the next run.
+pub fn save_ranks<P: AsRef<Path>>(path: P, players: &[PlayerStats]) -> io::Result<()> {
+    let entries: Vec<RankEntry> = players
+        .iter()
+        .enumerate()
+        .map(|(i, p)| RankEntry { usertype: p.usertype, userid: p.userid, rank: i + 1 })
+        .collect();
+    publish::replace(path.as_ref(), |writer| Ok(serde_json::to_writer(writer, &entries)?))
+}
+
+/// Looks up the previous rank of every player in `players`.
+pub fn previous_ranks(players: &[PlayerStats], ranks: &HashMap<(u8, i64), usize>) -> Vec<Option<usize>> {
+    players.iter().map(|p| ranks.get(&p.key()).copied()).collect()
+}
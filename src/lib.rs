@@ -0,0 +1,47 @@
+//! Readers, writers and report generators for CS2D `userstats.dat` and `stats.dat` files.
+//!
+//! The default `cli` feature builds the command-line tool. Libraries that only
+//! need the parser and the JSON, CSV and XML writers can turn it off; `templates`,
+//! `watch`, `server` and `config` add the rest piece by piece.
+
+pub mod admin;
+pub mod archive;
+pub mod charts;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod diff;
+pub mod error;
+pub mod leaderboard;
+pub mod output;
+#[cfg(feature = "templates")]
+pub mod pages;
+pub mod parser;
+pub mod publish;
+pub mod ranking;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "templates")]
+pub mod site;
+#[cfg(feature = "templates")]
+pub mod template;
+pub mod traffic;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod writer;
+
+pub use archive::{Archive, Snapshot};
+#[cfg(feature = "config")]
+pub use config::Config;
+pub use diff::diff_players;
+pub use error::ParseError;
+pub use leaderboard::Filter;
+pub use output::{Pagination, Report, write_csv, write_json, write_xml};
+#[cfg(feature = "templates")]
+pub use output::{write_html, write_md};
+pub use parser::{
+    Encoding, PlayerStats, TrafficStats, parse_stats, parse_userstats, parse_userstats_with, read_stats,
+    read_userstats, read_userstats_with,
+};
+pub use ranking::{SortError, SortSpec};
+pub use traffic::{Period, TrafficSummary};
+pub use writer::{save_stats, save_userstats, write_stats, write_userstats};
@@ -1,183 +1,1133 @@
-use clap::{Arg, Command};
-use std::path::Path;
-use std::time::Instant;
-use parser::{parse_userstats, parse_stats};
+use chrono::{DateTime, Local, TimeDelta};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use cs2d_stats_parser::server::ServerConfig;
+use regex::Regex;
+use cs2d_stats_parser::{
+    admin, config, diff_players, publish, Archive, Config, Filter, leaderboard, output, parse_stats, parse_userstats, parse_userstats_with, Encoding,
+    pages, pages::PageFormat, PlayerStats, Report, SortSpec, site, site::HistoryPoint, TrafficStats, TrafficSummary, parser, ranking, server, watch,
+};
+use std::fs;
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::iter;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use std::process;
-use notify::{Watcher, RecursiveMode, recommended_watcher, EventKind};
-use std::sync::mpsc::channel;
-use std::time::Duration;
 
-mod parser;
-mod output;
+/// Output formats: the file formats, then a folder for a static site.
+const FORMATS: [&str; 6] = ["html", "json", "csv", "md", "xml", "site"];
 
-pub fn generate_output(
-    userstats_path: &Path,
-    stats_path: &Path,
-    output: &str,
-    ext: &str,
-    sort: u8,
+/// Leaderboard settings taken from the command line.
+struct Settings {
+    sort: SortSpec,
     limit: u32,
-    title: &str,
+    title: String,
     pretty: bool,
-) {
-    let start_time = Instant::now();
+    encoding: Encoding,
+    ranks: Option<PathBuf>,
+    live: Option<String>,
+    traffic: bool,
+    charts: bool,
+    offline: bool,
+    template: Option<String>,
+    page_size: Option<usize>,
+    /// Format of every output, overriding their extensions.
+    format: Option<&'static str>,
+    /// How many previous versions of each output file to keep as backups.
+    keep_versions: Option<usize>,
+    filter: Filter,
+}
 
-    let mut players = match parse_userstats(userstats_path.to_str().unwrap()) {
-        Ok(players) => players,
-        Err(e) => {
-            eprintln!("Error parsing userstats: {}", e);
-            return;
+impl Settings {
+    fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        Ok(Settings {
+            sort: matches.get_one::<SortSpec>("sort").unwrap().clone(),
+            limit: *matches.get_one::<u32>("limit").unwrap(),
+            title: matches.get_one::<String>("title").unwrap().clone(),
+            pretty: matches.get_flag("pretty-print"),
+            encoding: matches.get_one::<String>("encoding").unwrap().parse().unwrap(),
+            ranks: matches.try_get_one::<String>("ranks").ok().flatten().map(PathBuf::from),
+            live: matches.try_get_one::<String>("live").ok().flatten().cloned(),
+            traffic: matches.try_get_one::<String>("report").ok().flatten().is_some_and(|r| r == "traffic"),
+            charts: !matches.get_flag("no-charts"),
+            offline: matches.get_flag("offline"),
+            page_size: matches.try_get_one::<u32>("page-size").ok().flatten().map(|n| *n as usize),
+            keep_versions: matches.try_get_one::<u32>("keep-versions").ok().flatten().map(|n| *n as usize),
+            format: matches
+                .try_get_one::<String>("format")
+                .ok()
+                .flatten()
+                .and_then(|format| FORMATS.into_iter().find(|f| f == format)),
+            template: match matches.get_one::<String>("template") {
+                Some(path) => Some(fs::read_to_string(path).map_err(|e| format!("cannot read template '{}': {}", path, e))?),
+                None => None,
+            },
+            filter: Filter {
+                min_time: *matches.get_one::<i32>("min-time").unwrap(),
+                min_kills: *matches.get_one::<i32>("min-kills").unwrap(),
+                only: matches.get_one::<u8>("only").copied(),
+                include: matches.get_one::<Regex>("include").cloned(),
+                exclude: matches.get_one::<Regex>("exclude").cloned(),
+                ignored: match matches.get_one::<String>("ignore") {
+                    Some(path) => leaderboard::load_ignore_list(path)
+                        .map_err(|e| format!("cannot read ignore list '{}': {}", path, e))?,
+                    None => Default::default(),
+                },
+            },
+        })
+    }
+}
+
+/// Returns the value of `result`, or prints its error and exits.
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}.", e);
+        process::exit(1);
+    })
+}
+
+/// An output path and the format it is written in.
+struct Target {
+    path: String,
+    format: &'static str,
+}
+
+/// The sorted leaderboard shared by every output of a run.
+struct Leaderboard {
+    /// Every ranked player, before the limit is applied.
+    players: Vec<PlayerStats>,
+    excluded: usize,
+    previous_ranks: Option<Vec<Option<usize>>>,
+}
+
+/// Checks that `settings` can be written to `output` as `format`.
+fn check_target(output: &str, format: &str, settings: &Settings) -> Result<(), String> {
+    let error = if output == "-" && (format == "site" || settings.page_size.is_some()) {
+        "only a single file can be written to stdout"
+    } else if settings.template.is_some() && (settings.traffic || !matches!(format, "html" | "md" | "site")) {
+        "--template only applies to HTML and Markdown leaderboards"
+    } else if settings.page_size.is_some() && (settings.traffic || !matches!(format, "html" | "md")) {
+        "--page-size only applies to HTML and Markdown leaderboards"
+    } else if settings.traffic && format == "site" {
+        "the traffic report is a single file; use an .html, .json, .csv, .md or .xml output"
+    } else {
+        return Ok(());
+    };
+    Err(error.to_string())
+}
+
+/// Writes `output` with `write`, or stdout when it is `-`.
+///
+/// Files are replaced atomically, after backing up the previous version when
+/// `keep` older versions are to be kept.
+fn write_output<F>(output: &Path, keep: Option<usize>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    if output == Path::new("-") {
+        let mut writer = BufWriter::new(io::stdout().lock());
+        write(&mut writer)?;
+        return writer.flush();
+    }
+    publish::replace_keeping(output, keep, |writer| write(writer))
+}
+
+/// Prints a progress message, on stderr when an output goes to stdout so it stays clean.
+fn progress(stdout: bool, message: impl Display) {
+    if stdout {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// Sorts and filters `players`, and updates the ranks file when one is set.
+fn rank_players(mut players: Vec<PlayerStats>, settings: &Settings) -> io::Result<Leaderboard> {
+    let excluded = leaderboard::rank(&mut players, &settings.filter, &settings.sort);
+    let previous_ranks = match &settings.ranks {
+        Some(path) if !settings.traffic => {
+            let ranks = leaderboard::load_ranks(path)?;
+            leaderboard::save_ranks(path, &players)?;
+            Some(leaderboard::previous_ranks(&players, &ranks))
         }
+        _ => None,
     };
+    Ok(Leaderboard { players, excluded, previous_ranks })
+}
+
+/// Writes the limited leaderboard, or the traffic report, to `output` as `ext`.
+fn write_report(
+    board: &Leaderboard,
+    traffic: &[TrafficStats],
+    output: &str,
+    ext: &str,
+    settings: &Settings,
+    archive: Option<&Archive>,
+) -> io::Result<()> {
+    if settings.traffic {
+        let summary = TrafficSummary::new(traffic);
+        return write_output(Path::new(output), settings.keep_versions, |writer| match ext {
+            "html" => output::write_traffic_html(writer, &settings.title, &summary, settings.offline),
+            "json" => output::write_traffic_json(writer, &summary, settings.pretty),
+            "csv"  => output::write_traffic_csv(writer, &summary),
+            "md"   => output::write_traffic_md(writer, &settings.title, &summary),
+            "xml"  => output::write_traffic_xml(writer, &summary),
+            _ => unreachable!(),
+        });
+    }
 
-    let users = players.len();
+    let limit = settings.limit.min(board.players.len() as u32) as usize;
+    let mut report = Report::new(&settings.title, &board.players[..limit], traffic, board.players.len());
+    report.excluded = board.excluded;
+    report.previous_ranks = board.previous_ranks.as_deref().map(|ranks| &ranks[..limit]);
+    report.live = settings.live.as_deref();
+    report.charts = settings.charts;
+    report.offline = settings.offline;
+    report.template = settings.template.as_deref();
 
-    match sort {
-        0 => players.sort_by_key(|p| -(p.score + p.kills - p.deaths)),
-        1 => players.sort_by_key(|p| -(p.assists + p.kills - p.deaths)),
-        2 => players.sort_by_key(|p| -(p.score + p.assists + p.deaths)),
-        _ => {}
+    if ext == "site" {
+        let history = match archive {
+            Some(archive) => load_history(archive, settings)?,
+            None => Vec::new(),
+        };
+        return site::write_site(Path::new(output), &report, &board.players, &history, settings.keep_versions);
+    }
+    if let Some(page_size) = settings.page_size {
+        let format = if ext == "md" { PageFormat::Markdown } else { PageFormat::Html };
+        return pages::write_pages(Path::new(output), &report, format, page_size, settings.keep_versions);
     }
 
-    let limit = limit.min(players.len() as u32) as usize;
-    players.truncate(limit);
+    write_output(Path::new(output), settings.keep_versions, |writer| match ext {
+        "html" => output::write_html(writer, &report),
+        "json" => output::write_json(writer, &report, settings.pretty),
+        "csv"  => output::write_csv(writer, &report),
+        "md"   => output::write_md(writer, &report),
+        "xml"  => output::write_xml(writer, &report),
+        _ => unreachable!(),
+    })
+}
+
+/// Ranks every snapshot in `archive` like the current leaderboard, oldest first.
+fn load_history(archive: &Archive, settings: &Settings) -> io::Result<Vec<HistoryPoint>> {
+    Ok(archive
+        .list()?
+        .iter()
+        .filter_map(|id| match archive.load(id) {
+            Ok(snapshot) => {
+                let mut players = snapshot.players;
+                leaderboard::rank(&mut players, &settings.filter, &settings.sort);
+                Some(HistoryPoint { taken_at: snapshot.taken_at, players })
+            }
+            Err(e) => {
+                eprintln!("Error loading snapshot '{}', skipping it: {}", id, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Parses the stats once and writes them to every target, reporting each one separately.
+///
+/// Without a `stats_path` the report has no traffic. With `retry`, files that fail
+/// to parse are read again a few times, as they are most likely still being written.
+fn generate_output(
+    userstats_path: &Path,
+    stats_path: Option<&Path>,
+    targets: &[Target],
+    settings: &Settings,
+    archive: Option<&Archive>,
+    retry: bool,
+) {
+    let mut start_time = Instant::now();
 
-    let traffic = match parse_stats(stats_path.to_str().unwrap()) {
-        Ok(traffic) => traffic,
+    let parse = || -> Result<_, String> {
+        let players = parse_userstats_with(userstats_path, settings.encoding)
+            .map_err(|e| format!("'{}': {}", userstats_path.display(), e))?;
+        let traffic = match stats_path {
+            Some(path) => parse_stats(path).map_err(|e| format!("'{}': {}", path.display(), e))?,
+            None => Vec::new(),
+        };
+        Ok((players, traffic))
+    };
+    let (players, traffic) = match if retry { watch::retry(parse) } else { parse() } {
+        Ok(parsed) => parsed,
         Err(e) => {
-            eprintln!("Error parsing stats: {}", e);
+            eprintln!("Error parsing {}", e);
             return;
         }
     };
 
-    let uptime = traffic.len();
-    let uploaded: u64 = traffic.iter().map(|t| t.uploaded_bytes as u64).sum();
-    let downloaded: u64 = traffic.iter().map(|t| t.downloaded_bytes as u64).sum();
+    if let Some(archive) = archive
+        && let Err(e) = archive.record(&players, &traffic)
+    {
+        eprintln!("Error archiving snapshot in '{}': {}", archive.dir.display(), e);
+    }
 
-    let result = match ext {
-        "html" => output::write_html(&players, output, title, uptime, uploaded, downloaded, users),
-        "json" => output::write_json(&players, output, pretty),
-        "csv"  => output::write_csv(&players, output),
-        "md"   => output::write_md(&players, output, title, uptime, uploaded, downloaded, users),
-        "xml"  => output::write_xml(&players, output),
-        _ => unreachable!(),
+    let board = match rank_players(players, settings) {
+        Ok(board) => board,
+        Err(e) => {
+            eprintln!("Error updating ranks: {}", e);
+            return;
+        }
     };
 
-    if let Err(e) = result {
-        eprintln!("Error writing {}: {}", ext.to_uppercase(), e);
-        return;
+    let stdout = targets.iter().any(|target| target.path == "-");
+    if targets.len() > 1 {
+        progress(stdout, format_args!("Parsed stats within {} ms", start_time.elapsed().as_millis()));
+    }
+    for target in targets {
+        if targets.len() > 1 {
+            start_time = Instant::now();
+        }
+        match write_report(&board, &traffic, &target.path, target.format, settings, archive) {
+            Ok(()) => progress(stdout, format_args!("Generated '{}' within {} ms", target.path, start_time.elapsed().as_millis())),
+            Err(e) => eprintln!("Error writing '{}': {}", target.path, e),
+        }
     }
+}
 
-    println!("Generated '{}' within {} ms", output, start_time.elapsed().as_millis());
+/// Returns the output format for `output`: `--format` if given, else the extension.
+/// Fails if the extension is not supported or `settings` cannot be written in that format.
+/// Paths without an extension are folders for a static site.
+fn output_format(output: &str, settings: &Settings) -> Result<&'static str, String> {
+    let format = match (settings.format, Path::new(output).extension()) {
+        (Some(format), _) => format,
+        _ if output == "-" => return Err("pass --format to write to stdout".to_string()),
+        (None, Some(ext)) => {
+            let ext = ext.to_str().unwrap_or("unknown");
+            FORMATS[..5].iter().find(|format| **format == ext).ok_or_else(|| {
+                format!(
+                    "Invalid output format '{}'. Supported formats: {}, or a folder without extension for a static site",
+                    ext,
+                    FORMATS[..5].join(", ")
+                )
+            })?
+        }
+        (None, None) => "site",
+    };
+    check_target(output, format, settings)?;
+    Ok(format)
 }
 
-fn main() {
-    let matches = Command::new("CS2D Stats Parser")
-        .version("3.0.2")
-        .author("Ernest Paśnik <https://github.com/ernestpasnik/cs2d-stats-parser>")
-        .about("This tool parses CS2D stats and exports them as HTML, JSON, CSV, Markdown, or XML.")
-        .arg(Arg::new("folder")
-            .help("Path to the folder containing 'userstats.dat'")
-            .index(1)
-            .required(true))
-        .arg(Arg::new("output")
-            .index(2)
-            .required(true)
-            .help("Output file (must end with .html, .json, .csv, .md, or .xml)"))
-        .arg(Arg::new("sort")
+/// Options shared by every command that writes a leaderboard.
+fn report_args() -> [Arg; 20] {
+    [
+        Arg::new("report")
+            .long("report")
+            .value_parser(["players", "traffic"])
+            .default_value("players")
+            .help("Report to generate: the player leaderboard, or daily/monthly/hourly traffic from 'stats.dat'"),
+        Arg::new("format")
+            .short('f')
+            .long("format")
+            .value_parser(FORMATS)
+            .help("Format of every output, instead of taking it from the extension (required for '-', which writes to stdout)"),
+        Arg::new("sort")
             .short('s')
             .long("sort")
-            .value_parser(clap::value_parser!(u8).range(0..=2))
+            .value_parser(|s: &str| s.parse::<SortSpec>().map_err(|e| e.to_string()))
             .default_value("1")
-            .help("Sort leaderboard:\n0 = score+kills-deaths\n1 = assists+kills-deaths\n2 = score+assists+deaths"))
-        .arg(Arg::new("limit")
+            .help(format!(
+                "Sort leaderboard:\n0 = score+kills-deaths\n1 = assists+kills-deaths\n2 = score+assists+deaths\n\
+                 or comma-separated expressions with optional asc/desc, e.g.\n\
+                 \"kills*2 + assists - deaths, time asc\"\n\
+                 Fields: {}\nFunctions: {}\n\
+                 Division by zero gives 0; kd is kills when deaths is 0, like kills/max(deaths,1)",
+                ranking::FIELDS.join(", "),
+                ranking::FUNCTIONS.join(", ")
+            )),
+        Arg::new("limit")
             .short('l')
             .long("limit")
             .value_parser(clap::value_parser!(u32).range(1..=100000))
             .default_value("100")
-            .help("Limit players in the generated output"))
-        .arg(Arg::new("title")
+            .help("Limit players in the generated output"),
+        Arg::new("title")
             .short('t')
             .long("title")
             .value_parser(clap::value_parser!(String))
             .default_value("CS2D Server")
-            .help("Title to display in the HTML/Markdown report"))
-        .arg(Arg::new("pretty-print")
+            .help("Title to display in the HTML/Markdown report"),
+        Arg::new("pretty-print")
             .short('p')
             .long("pretty-print")
             .help("Enable pretty-printing for JSON output to improve readability")
-            .action(clap::ArgAction::SetTrue))
-        .arg(Arg::new("watch")
-            .short('w')
-            .long("watch")
-            .help("Monitor 'userstats.dat' for changes and regenerate output when modified")
-            .action(clap::ArgAction::SetTrue))
-        .get_matches();
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("encoding")
+            .short('e')
+            .long("encoding")
+            .value_parser(["latin1", "cp1251", "lossy"])
+            .default_value("latin1")
+            .help("Fallback encoding for player names that are not valid UTF-8"),
+        Arg::new("ranks")
+            .short('r')
+            .long("ranks")
+            .value_name("file")
+            .help("Show rank movement against the ranks saved in this file by the previous run, then update it"),
+        Arg::new("min-time")
+            .long("min-time")
+            .value_name("duration")
+            .value_parser(leaderboard::parse_duration)
+            .default_value("0")
+            .help("Only rank players with at least this much playtime (e.g. 600, 30m, 2h)"),
+        Arg::new("min-kills")
+            .long("min-kills")
+            .value_name("kills")
+            .value_parser(clap::value_parser!(i32))
+            .default_value("0")
+            .help("Only rank players with at least this many kills"),
+        Arg::new("only")
+            .long("only")
+            .value_name("usertype")
+            .value_parser(parse_usertype)
+            .help("Only rank 'steam' or 'usgn' players"),
+        Arg::new("include")
+            .long("include")
+            .value_name("regex")
+            .value_parser(|s: &str| Regex::new(s).map_err(|e| e.to_string()))
+            .help("Only rank players whose name matches this regular expression"),
+        Arg::new("exclude")
+            .long("exclude")
+            .value_name("regex")
+            .value_parser(|s: &str| Regex::new(s).map_err(|e| e.to_string()))
+            .help("Leave out players whose name matches this regular expression"),
+        Arg::new("ignore")
+            .long("ignore")
+            .value_name("file")
+            .help("Leave out the accounts listed in this file (one 'userid', 'usgn:userid' or 'steam:userid' per line)"),
+        Arg::new("no-charts")
+            .long("no-charts")
+            .action(clap::ArgAction::SetTrue)
+            .help("Leave the traffic and player activity charts out of the HTML report"),
+        Arg::new("page-size")
+            .long("page-size")
+            .value_name("rows")
+            .value_parser(clap::value_parser!(u32).range(1..))
+            .help("Split the HTML or Markdown leaderboard into pages of this many rows (name.html, name-2.html, ...)"),
+        Arg::new("keep-versions")
+            .long("keep-versions")
+            .value_name("count")
+            .value_parser(clap::value_parser!(u32).range(1..))
+            .help("Keep this many previous versions of each output file as timestamped .bak files next to it"),
+        Arg::new("template")
+            .long("template")
+            .value_name("file")
+            .help("Render the HTML or Markdown leaderboard with this minijinja template instead of the built-in layout"),
+        Arg::new("offline")
+            .long("offline")
+            .action(clap::ArgAction::SetTrue)
+            .help("Embed the stylesheet and scripts in the HTML report so it loads nothing from the network"),
+        Arg::new("live")
+            .long("live")
+            .value_name("url")
+            .help("Make the HTML output update its table from this 'serve' events URL (e.g. http://host:8080/events)"),
+    ]
+}
 
-    let folder = matches.get_one::<String>("folder").unwrap();
+fn diff_command() -> Command {
+    Command::new("diff")
+        .about("Build a leaderboard of the activity between two 'userstats.dat' snapshots")
+        .arg(Arg::new("old")
+            .help("Older 'userstats.dat' file")
+            .required(true))
+        .arg(Arg::new("new")
+            .help("Newer 'userstats.dat' file")
+            .required(true))
+        .arg(Arg::new("output")
+            .required(true)
+            .help("Output file (.html, .json, .csv, .md or .xml), a folder without extension for a static site, or - for stdout"))
+        .arg(Arg::new("stats")
+            .long("stats")
+            .help("'stats.dat' to take traffic from [default: next to the newer file]"))
+        .args(report_args())
+}
+
+fn run_diff(matches: &ArgMatches) {
+    let start_time = Instant::now();
+    let old_path = Path::new(matches.get_one::<String>("old").unwrap());
+    let new_path = Path::new(matches.get_one::<String>("new").unwrap());
     let output = matches.get_one::<String>("output").unwrap();
-    let sort = *matches.get_one::<u8>("sort").unwrap();
-    let limit = *matches.get_one::<u32>("limit").unwrap();
-    let title = matches.get_one::<String>("title").unwrap();
-    let pretty = matches.get_flag("pretty-print");
-    let watch = matches.get_flag("watch");
-
-    if !Path::new(folder).is_dir() {
-        eprintln!("Error: '{}' is not a valid folder.", folder);
+    let settings = or_exit(Settings::from_matches(matches));
+    let ext = or_exit(output_format(output, &settings));
+
+    let parse = |path: &Path| match parse_userstats_with(path, settings.encoding) {
+        Ok(players) => players,
+        Err(e) => {
+            eprintln!("Error parsing '{}': {}", path.display(), e);
+            process::exit(1);
+        }
+    };
+    let players = diff_players(&parse(old_path), &parse(new_path));
+
+    // Only count traffic recorded after the older snapshot was taken.
+    let stats_path = match matches.get_one::<String>("stats") {
+        Some(path) => PathBuf::from(path),
+        None => new_path.with_file_name("stats.dat"),
+    };
+    let since = fs::metadata(old_path)
+        .and_then(|m| m.modified())
+        .map(|t| DateTime::<Local>::from(t).naive_local())
+        .ok();
+    let traffic: Vec<TrafficStats> = match parse_stats(&stats_path) {
+        Ok(traffic) => traffic
+            .into_iter()
+            .filter(|t| match (t.datetime(), since) {
+                (Some(time), Some(since)) => time >= since,
+                _ => true,
+            })
+            .collect(),
+        Err(e) if matches.contains_id("stats") => {
+            eprintln!("Error parsing stats: {}", e);
+            process::exit(1);
+        }
+        Err(_) => Vec::new(),
+    };
+
+    let result = rank_players(players, &settings).and_then(|board| write_report(&board, &traffic, output, ext, &settings, None));
+    if let Err(e) = result {
+        eprintln!("Error writing {}: {}", ext.to_uppercase(), e);
+        process::exit(1);
+    }
+
+    progress(output == "-", format_args!("Generated '{}' within {} ms", output, start_time.elapsed().as_millis()));
+}
+
+fn archive_command() -> Command {
+    Command::new("archive")
+        .about("List or export snapshots stored with --archive")
+        .arg(Arg::new("dir")
+            .help("Archive directory")
+            .required(true))
+        .subcommand_required(true)
+        .subcommand(Command::new("list")
+            .about("List stored snapshots"))
+        .subcommand(Command::new("export")
+            .about("Write the leaderboard of a stored snapshot")
+            .arg(Arg::new("snapshot")
+                .required(true)
+                .help("Snapshot id as shown by 'list', or 'latest'"))
+            .arg(Arg::new("output")
+                .required(true)
+                .help("Output file (.html, .json, .csv, .md or .xml), a folder without extension for a static site, or - for stdout"))
+            .arg(Arg::new("since")
+                .long("since")
+                .help("Rank only the activity after this older snapshot"))
+            .args(report_args()))
+}
+
+fn run_archive(matches: &ArgMatches) {
+    let dir = matches.get_one::<String>("dir").unwrap();
+    if !Path::new(dir).is_dir() {
+        eprintln!("Error: '{}' is not a valid folder.", dir);
         process::exit(1);
     }
+    let archive = match Archive::open(dir) {
+        Ok(archive) => archive,
+        Err(e) => {
+            eprintln!("Error opening archive '{}': {}", dir, e);
+            process::exit(1);
+        }
+    };
+    let load = |id: &str| match archive.load(id) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            eprintln!("Error loading snapshot '{}': {}", id, e);
+            process::exit(1);
+        }
+    };
+
+    match matches.subcommand() {
+        Some(("list", _)) => {
+            let ids = archive.list().unwrap_or_else(|e| {
+                eprintln!("Error reading archive '{}': {}", dir, e);
+                process::exit(1);
+            });
+            let mut failed = false;
+            for id in ids {
+                match archive.load(&id) {
+                    Ok(snapshot) => println!(
+                        "{}  {}  {} players, {} new traffic hours",
+                        id,
+                        snapshot.taken_at.format("%d %b %Y at %H:%M:%S"),
+                        snapshot.players.len(),
+                        snapshot.traffic.len()
+                    ),
+                    Err(e) => {
+                        eprintln!("Error loading snapshot '{}': {}", id, e);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                process::exit(1);
+            }
+        }
+        Some(("export", m)) => {
+            let start_time = Instant::now();
+            let id = m.get_one::<String>("snapshot").unwrap();
+            let output = m.get_one::<String>("output").unwrap();
+            let settings = or_exit(Settings::from_matches(m));
+            let ext = or_exit(output_format(output, &settings));
+
+            let snapshot = load(id);
+            let mut traffic = archive.traffic_until(id).unwrap_or_else(|e| {
+                eprintln!("Error reading archive '{}': {}", dir, e);
+                process::exit(1);
+            });
+            let players = match m.get_one::<String>("since") {
+                Some(since) => {
+                    let older = load(since);
+                    traffic.retain(|t| t.datetime().is_none_or(|time| time >= older.taken_at));
+                    diff_players(&older.players, &snapshot.players)
+                }
+                None => snapshot.players,
+            };
+
+            let result = rank_players(players, &settings).and_then(|board| write_report(&board, &traffic, output, ext, &settings, None));
+            if let Err(e) = result {
+                eprintln!("Error writing {}: {}", ext.to_uppercase(), e);
+                process::exit(1);
+            }
+            progress(output == "-", format_args!("Generated '{}' within {} ms", output, start_time.elapsed().as_millis()));
+        }
+        _ => unreachable!(),
+    }
+}
 
+fn serve_command() -> Command {
+    Command::new("serve")
+        .about("Serve a live leaderboard page and JSON API over HTTP")
+        .arg(Arg::new("folder")
+            .help("Path to the folder containing 'userstats.dat' and 'stats.dat'")
+            .required(true))
+        .arg(Arg::new("bind")
+            .short('b')
+            .long("bind")
+            .value_name("address")
+            .default_value("127.0.0.1:8080")
+            .help("Address and port to listen on"))
+        .arg(Arg::new("live")
+            .long("live")
+            .action(clap::ArgAction::SetTrue)
+            .help("Update the leaderboard page in place whenever the stats change"))
+        .arg(Arg::new("interval")
+            .long("interval")
+            .value_name("duration")
+            .value_parser(parse_interval)
+            .help("Also check the size and modification time of the stats files this often (e.g. 30s, 5m), for folders where change events never arrive"))
+        .args(report_args().into_iter().filter(|arg| !matches!(arg.get_id().as_str(), "ranks" | "live" | "report" | "page-size" | "format" | "keep-versions")))
+}
+
+fn run_serve(matches: &ArgMatches) {
+    let folder = matches.get_one::<String>("folder").unwrap();
+    let bind = matches.get_one::<String>("bind").unwrap();
+    let settings = or_exit(Settings::from_matches(matches));
+
+    let config = ServerConfig {
+        userstats_path: Path::new(folder).join("userstats.dat"),
+        stats_path: Path::new(folder).join("stats.dat"),
+        encoding: settings.encoding,
+        title: settings.title,
+        sort: settings.sort,
+        limit: settings.limit as usize,
+        filter: settings.filter,
+        pretty: settings.pretty,
+        live: matches.get_flag("live"),
+        charts: settings.charts,
+        offline: settings.offline,
+        template: settings.template,
+        interval: matches.get_one::<Duration>("interval").copied(),
+    };
+
+    println!("Serving '{}' on http://{}/", folder, bind);
+    if let Err(e) = server::serve(bind, config) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match leaderboard::parse_duration(s)? {
+        0 => Err("the interval must be at least one second".to_string()),
+        seconds => Ok(Duration::from_secs(seconds as u64)),
+    }
+}
+
+fn parse_usertype(s: &str) -> Result<u8, String> {
+    parser::parse_usertype(s).ok_or_else(|| "expected 'usgn', 'steam', 0 or 1".to_string())
+}
+
+fn describe_player(key: (u8, i64)) -> String {
+    match key.0 {
+        1 => format!("steam:{}", key.1),
+        _ => format!("usgn:{}", key.1),
+    }
+}
+
+fn player_args(usertype: &'static str, userid: &'static str) -> [Arg; 2] {
+    [
+        Arg::new(usertype)
+            .required(true)
+            .value_parser(parse_usertype)
+            .help("Account type: 'usgn' (0) or 'steam' (1)"),
+        Arg::new(userid)
+            .required(true)
+            .value_parser(clap::value_parser!(i64))
+            .help("USGN or Steam account ID"),
+    ]
+}
+
+fn player_key(matches: &ArgMatches, usertype: &str, userid: &str) -> (u8, i64) {
+    (*matches.get_one::<u8>(usertype).unwrap(), *matches.get_one::<i64>(userid).unwrap())
+}
+
+fn admin_command() -> Command {
+    Command::new("admin")
+        .about("Edit players in 'userstats.dat' (a timestamped backup is kept)")
+        .arg(Arg::new("folder")
+            .help("Path to the folder containing 'userstats.dat'")
+            .required(true))
+        .arg(Arg::new("force")
+            .short('f')
+            .long("force")
+            .help("Edit the file even if the CS2D server seems to be running")
+            .action(clap::ArgAction::SetTrue))
+        .subcommand_required(true)
+        .subcommand(Command::new("reset")
+            .about("Reset all stats of a player")
+            .args(player_args("usertype", "userid")))
+        .subcommand(Command::new("remove")
+            .about("Remove a player")
+            .args(player_args("usertype", "userid")))
+        .subcommand(Command::new("rename")
+            .about("Rename a player")
+            .args(player_args("usertype", "userid"))
+            .arg(Arg::new("name").required(true).help("New player name")))
+        .subcommand(Command::new("merge")
+            .about("Add the stats of one account to another and remove the first")
+            .args(player_args("usertype", "userid"))
+            .args(player_args("into-usertype", "into-userid")))
+}
+
+fn run_admin(matches: &ArgMatches) {
+    let folder = matches.get_one::<String>("folder").unwrap();
     let userstats_path = Path::new(folder).join("userstats.dat");
     if !userstats_path.exists() {
         eprintln!("Error: 'userstats.dat' not found in '{}'.", folder);
         process::exit(1);
     }
 
-    let stats_path = Path::new(folder).join("stats.dat");
-    if !stats_path.exists() {
-        eprintln!("Error: 'stats.dat' not found in '{}'.", folder);
+    if !matches.get_flag("force")
+        && let Some(reason) = admin::busy_reason(&userstats_path)
+    {
+        eprintln!("Error: {}. The server may overwrite the changes; stop it or use --force.", reason);
         process::exit(1);
     }
 
-    let ext = Path::new(output)
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("unknown");
+    let mut players = match parse_userstats(&userstats_path) {
+        Ok(players) => players,
+        Err(e) => {
+            eprintln!("Error parsing userstats: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let (key, done, action) = match matches.subcommand() {
+        Some(("reset", m)) => {
+            let key = player_key(m, "usertype", "userid");
+            (key, admin::reset(&mut players, key), format!("Reset stats of {}", describe_player(key)))
+        }
+        Some(("remove", m)) => {
+            let key = player_key(m, "usertype", "userid");
+            (key, admin::remove(&mut players, key), format!("Removed {}", describe_player(key)))
+        }
+        Some(("rename", m)) => {
+            let key = player_key(m, "usertype", "userid");
+            let name = m.get_one::<String>("name").unwrap();
+            (key, admin::rename(&mut players, key, name), format!("Renamed {} to '{}'", describe_player(key), name))
+        }
+        Some(("merge", m)) => {
+            let (from, into) = (player_key(m, "usertype", "userid"), player_key(m, "into-usertype", "into-userid"));
+            if from == into {
+                eprintln!("Error: cannot merge {} into itself.", describe_player(from));
+                process::exit(1);
+            }
+            let missing = if players.iter().any(|p| p.key() == from) { into } else { from };
+            let action = format!("Merged {} into {}", describe_player(from), describe_player(into));
+            match admin::merge(&mut players, from, into) {
+                Ok(done) => (missing, done, action),
+                Err(e) => {
+                    eprintln!("Error: cannot merge {} into {}: {}.", describe_player(from), describe_player(into), e);
+                    process::exit(1);
+                }
+            }
+        }
+        _ => unreachable!(),
+    };
+
+    if !done {
+        eprintln!("Error: player {} not found in '{}'.", describe_player(key), userstats_path.display());
+        process::exit(1);
+    }
+
+    match admin::save_with_backup(&userstats_path, &players) {
+        Ok(backup) => println!("{}. Backup saved to '{}'.", action, backup.display()),
+        Err(e) => {
+            eprintln!("Error writing '{}': {}", userstats_path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+/// The command line of the default command and its subcommands.
+fn cli() -> Command {
+    Command::new("CS2D Stats Parser")
+        .version("3.0.2")
+        .author("Ernest Paśnik <https://github.com/ernestpasnik/cs2d-stats-parser>")
+        .about("This tool parses CS2D stats and exports them as HTML, JSON, CSV, Markdown, or XML.")
+        .arg(Arg::new("folder")
+            .help("Path to the folder containing 'userstats.dat' and 'stats.dat'")
+            .index(1)
+            .required_unless_present_any(["userstats", "config"]))
+        .arg(Arg::new("output")
+            .index(2)
+            .help("Output file (.html, .json, .csv, .md or .xml), a folder without extension for a static site, or - for stdout"))
+        .arg(Arg::new("outputs")
+            .short('o')
+            .long("output")
+            .value_name("output")
+            .action(clap::ArgAction::Append)
+            .help("Output file or folder like <output>; repeat to write several outputs from one parse"))
+        .arg(Arg::new("userstats")
+            .long("userstats")
+            .value_name("file")
+            .help("'userstats.dat' to read instead of the one in <folder>"))
+        .arg(Arg::new("stats")
+            .long("stats")
+            .value_name("file")
+            .help("'stats.dat' to read instead of the one in <folder>; without either, the report has no traffic"))
+        .args(report_args())
+        .arg(Arg::new("watch")
+            .short('w')
+            .long("watch")
+            .help("Monitor 'userstats.dat' and 'stats.dat' for changes and regenerate output when they are modified")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("interval")
+            .long("interval")
+            .value_name("duration")
+            .value_parser(parse_interval)
+            .help("Check the size and modification time of the stats files this often (e.g. 30s, 5m) and regenerate output when they changed; works with or without --watch, e.g. on network mounts"))
+        .arg(Arg::new("archive")
+            .long("archive")
+            .value_name("dir")
+            .help("Store a snapshot of the parsed stats in this folder on every run"))
+        .arg(Arg::new("archive-keep")
+            .long("archive-keep")
+            .value_name("count")
+            .requires("archive")
+            .value_parser(clap::value_parser!(usize))
+            .help("Keep at most this many archived snapshots"))
+        .arg(Arg::new("archive-days")
+            .long("archive-days")
+            .value_name("days")
+            .requires("archive")
+            .value_parser(clap::value_parser!(i64).range(1..))
+            .help("Delete archived snapshots older than this many days"))
+        .arg(Arg::new("config")
+            .short('c')
+            .long("config")
+            .value_name("file")
+            .help("Run the profiles of this TOML configuration file; options on the command line override theirs"))
+        .arg(Arg::new("profile")
+            .short('P')
+            .long("profile")
+            .value_name("name")
+            .requires("config")
+            .action(clap::ArgAction::Append)
+            .help("Only run this profile of the configuration file [default: all]; can be repeated"))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(admin_command())
+        .subcommand(diff_command())
+        .subcommand(archive_command())
+        .subcommand(serve_command())
+        .subcommand(config_command())
+}
+
+/// One leaderboard to regenerate, resolved from the command line or a profile.
+struct Job {
+    userstats_path: PathBuf,
+    stats_path: Option<PathBuf>,
+    targets: Vec<Target>,
+    settings: Settings,
+    archive: Option<Archive>,
+    /// How to notice changes to the stats files after the first run, if at all.
+    follow: Option<watch::Triggers>,
+}
+
+impl Job {
+    /// Checks the inputs and outputs given in `matches` of the default command.
+    /// With `check_only`, nothing is created on disk.
+    fn from_matches(matches: &ArgMatches, check_only: bool) -> Result<Self, String> {
+        // With --userstats the folder is optional, so a single positional is the output.
+        let (folder, output) = match (matches.get_one::<String>("folder"), matches.get_one::<String>("output")) {
+            (Some(output), None) if matches.contains_id("userstats") => (None, Some(output)),
+            positionals => positionals,
+        };
+        let settings = Settings::from_matches(matches)?;
+        let targets = output
+            .into_iter()
+            .chain(matches.get_many::<String>("outputs").into_iter().flatten())
+            .map(|path| Ok(Target { path: path.clone(), format: output_format(path, &settings)? }))
+            .collect::<Result<Vec<_>, String>>()?;
+        if targets.is_empty() {
+            return Err("no output given; pass <output> or one or more --output".to_string());
+        }
+
+        if let Some(folder) = folder
+            && !Path::new(folder).is_dir()
+        {
+            return Err(format!("'{}' is not a valid folder", folder));
+        }
+
+        let userstats_path = match (matches.get_one::<String>("userstats"), folder) {
+            (Some(path), _) => PathBuf::from(path),
+            (None, Some(folder)) => Path::new(folder).join("userstats.dat"),
+            (None, None) => return Err("no input given; pass <folder> or --userstats".to_string()),
+        };
+        if !userstats_path.is_file() {
+            return Err(format!("'{}' not found", userstats_path.display()));
+        }
+
+        // The leaderboard can do without 'stats.dat' when it is not in the folder.
+        let stats_path = match (matches.get_one::<String>("stats"), folder) {
+            (Some(path), _) => Some(PathBuf::from(path)),
+            (None, Some(folder)) => Some(Path::new(folder).join("stats.dat")).filter(|path| path.exists() || settings.traffic),
+            (None, None) => None,
+        };
+        match &stats_path {
+            Some(path) if !path.is_file() => return Err(format!("'{}' not found", path.display())),
+            None if settings.traffic => {
+                return Err("the traffic report needs 'stats.dat'; pass <folder> or --stats".to_string());
+            }
+            _ => {}
+        }
+
+        let archive = match matches.get_one::<String>("archive") {
+            Some(dir) => {
+                let archive = if check_only {
+                    Archive::check(dir).map(|()| Archive { dir: PathBuf::from(dir), keep: None, max_age: None })
+                } else {
+                    Archive::open(dir)
+                };
+                let archive = archive.map_err(|e| format!("cannot open archive '{}': {}", dir, e))?;
+                Some(Archive {
+                    keep: matches.get_one::<usize>("archive-keep").copied(),
+                    max_age: matches.get_one::<i64>("archive-days").map(|days| TimeDelta::days(*days)),
+                    ..archive
+                })
+            }
+            None => None,
+        };
+
+        let (events, interval) = (matches.get_flag("watch"), matches.get_one::<Duration>("interval").copied());
+        let follow = (events || interval.is_some()).then_some(watch::Triggers { events, interval });
+        Ok(Job { userstats_path, stats_path, targets, settings, archive, follow })
+    }
 
-    let supported_formats = ["html", "json", "csv", "md", "xml"];
-    if !supported_formats.contains(&ext) {
-        eprintln!(
-            "Error: Invalid output format '{}'. Supported formats: {}.",
-            ext,
-            supported_formats.join(", ")
+    fn run(&self, retry: bool) {
+        generate_output(
+            &self.userstats_path,
+            self.stats_path.as_deref(),
+            &self.targets,
+            &self.settings,
+            self.archive.as_ref(),
+            retry,
         );
+    }
+}
+
+/// Parses the command line `args` again with the options of a configuration profile added.
+/// Options given on the command line itself (in `matches`) take precedence over the profile.
+fn profile_matches(args: &[OsString], matches: &ArgMatches, options: &config::Options) -> Result<ArgMatches, String> {
+    let command = cli();
+    let mut args = args.to_vec();
+    for (key, values) in options {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key))
+            .or_else(|| command.get_arguments().find(|arg| arg.is_positional() && arg.get_id() == key))
+            .filter(|arg| !matches!(arg.get_id().as_str(), "config" | "profile" | "help" | "version"))
+            .ok_or_else(|| format!("unknown option '{}'", key))?;
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        match (arg.get_long(), arg.get_action()) {
+            (None, _) => args.extend(values.iter().map(OsString::from)),
+            (Some(long), ArgAction::SetTrue) => match values.as_slice() {
+                [value] if value == "true" => args.push(format!("--{}", long).into()),
+                [value] if value == "false" => {}
+                _ => return Err(format!("option '{}' takes true or false", key)),
+            },
+            (Some(long), _) => args.extend(values.iter().map(|value| format!("--{}={}", long, value).into())),
+        }
+    }
+    command.try_get_matches_from(args).map_err(|e| {
+        let message = e.render().to_string();
+        message.lines().next().unwrap_or_default().trim_start_matches("error: ").to_string()
+    })
+}
+
+/// The jobs of the profiles selected with --profile, or of all profiles, by name.
+fn profile_jobs(args: &[OsString], matches: &ArgMatches, path: &str, check_only: bool) -> Vec<(String, Result<Job, String>)> {
+    let config = or_exit(Config::load(path).map_err(|e| format!("cannot read '{}': {}", path, e)));
+    let names: Vec<String> = match matches.get_many::<String>("profile") {
+        Some(names) => names.cloned().collect(),
+        None => config.profiles.keys().cloned().collect(),
+    };
+    names
+        .into_iter()
+        .map(|name| {
+            let job = match config.profiles.get(&name) {
+                Some(options) => profile_matches(args, matches, options).and_then(|m| Job::from_matches(&m, check_only)),
+                None => Err(format!(
+                    "no such profile; '{}' has {}",
+                    path,
+                    config.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )),
+            };
+            (name, job)
+        })
+        .collect()
+}
+
+fn config_command() -> Command {
+    Command::new("config")
+        .about("Work with configuration files of server profiles")
+        .subcommand_required(true)
+        .subcommand(Command::new("check")
+            .about("Check that every profile of a configuration file can run")
+            .arg(Arg::new("file")
+                .required(true)
+                .help("TOML configuration file")))
+}
+
+fn run_config(matches: &ArgMatches) {
+    let Some(("check", m)) = matches.subcommand() else { unreachable!() };
+    let path = m.get_one::<String>("file").unwrap();
+    // Check the profiles as if they were run without any other options.
+    let args: Vec<OsString> = ["cs2d-stats-parser", "--config", path].map(OsString::from).into();
+    let matches = cli().get_matches_from(&args);
+
+    let mut valid = true;
+    for (name, job) in profile_jobs(&args, &matches, path, true) {
+        match job {
+            Ok(_) => println!("Profile '{}' is valid.", name),
+            Err(e) => {
+                eprintln!("Error in profile '{}': {}.", name, e);
+                valid = false;
+            }
+        }
+    }
+    if !valid {
         process::exit(1);
     }
+}
 
-    generate_output(&userstats_path, &stats_path, output, ext, sort, limit, title, pretty);
+fn main() {
+    let matches = cli().get_matches();
 
-    if watch {
-        println!("Monitoring '{}' for changes...", userstats_path.display());
-        let (tx, rx) = channel();
+    match matches.subcommand() {
+        Some(("admin", admin_matches)) => return run_admin(admin_matches),
+        Some(("diff", diff_matches)) => return run_diff(diff_matches),
+        Some(("archive", archive_matches)) => return run_archive(archive_matches),
+        Some(("serve", serve_matches)) => return run_serve(serve_matches),
+        Some(("config", config_matches)) => return run_config(config_matches),
+        _ => {}
+    }
 
-        let mut watcher = recommended_watcher(tx).expect("Failed to create file watcher");
-        watcher
-            .watch(&userstats_path, RecursiveMode::NonRecursive)
-            .expect("Failed to watch file");
+    let Some(config) = matches.get_one::<String>("config") else {
+        let job = or_exit(Job::from_matches(&matches, false));
+        job.run(false);
+        if job.follow.is_some() {
+            watch_jobs(&[job]);
+        }
+        return;
+    };
 
-        loop {
-            match rx.recv_timeout(Duration::from_secs(1)) {
-                Ok(Ok(event)) => {
-                    if let EventKind::Modify(_) = event.kind {
-                        println!("File change detected. Regenerating output...");
-                        generate_output(&userstats_path, &stats_path, output, ext, sort, limit, title, pretty);
-                    }
-                }
-                Ok(Err(e)) => {
-                    eprintln!("Watcher error: {}", e);
-                }
-                Err(_) => {}
+    let mut failed = false;
+    let mut jobs = Vec::new();
+    let args: Vec<OsString> = env::args_os().collect();
+    for (name, job) in profile_jobs(&args, &matches, config, false) {
+        match job {
+            Ok(job) => {
+                progress(job.targets.iter().any(|t| t.path == "-"), format_args!("Running profile '{}'...", name));
+                job.run(false);
+                jobs.push(job);
+            }
+            Err(e) => {
+                eprintln!("Error in profile '{}': {}.", name, e);
+                failed = true;
             }
         }
     }
+
+    if jobs.iter().any(|job| job.follow.is_some()) {
+        watch_jobs(&jobs);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Regenerates the outputs of every watched job whenever its stats files change.
+///
+/// Jobs are regenerated independently, and a folder that cannot be watched or
+/// read is reported without stopping the others.
+fn watch_jobs(jobs: &[Job]) {
+    let jobs: Vec<&Job> = jobs.iter().filter(|job| job.follow.is_some()).collect();
+    // Every watched file, with the index of the job it belongs to and that job's own triggers.
+    let files: Vec<(&Path, usize, watch::Triggers)> = jobs
+        .iter()
+        .enumerate()
+        .flat_map(|(j, job)| {
+            let follow = job.follow.unwrap();
+            iter::once(job.userstats_path.as_path()).chain(job.stats_path.as_deref()).map(move |path| (path, j, follow))
+        })
+        .collect();
+    let paths: Vec<(&Path, watch::Triggers)> = files.iter().map(|(path, _, triggers)| (*path, *triggers)).collect();
+    let stdout = jobs.iter().flat_map(|job| &job.targets).any(|target| target.path == "-");
+
+    for (path, triggers) in &paths {
+        match (triggers.events, triggers.interval) {
+            (true, Some(interval)) => progress(
+                stdout,
+                format_args!("Monitoring '{}' for changes, checking every {} s...", path.display(), interval.as_secs()),
+            ),
+            (false, Some(interval)) => progress(
+                stdout,
+                format_args!("Checking '{}' for changes every {} s...", path.display(), interval.as_secs()),
+            ),
+            _ => progress(stdout, format_args!("Monitoring '{}' for changes...", path.display())),
+        }
+    }
+    let result = watch::watch(
+        &paths,
+        |changed| {
+            for (j, job) in jobs.iter().enumerate() {
+                if let Some((path, _, _)) = changed.iter().map(|i| files[*i]).find(|(_, owner, _)| *owner == j) {
+                    progress(stdout, format_args!("Change detected in '{}'. Regenerating output...", path.display()));
+                    job.run(true);
+                }
+            }
+        },
+        |e| eprintln!("Watcher error: {}", e),
+    );
+    if let Err(e) = result {
+        eprintln!("Error: cannot start watching: {}", e);
+        process::exit(1);
+    }
 }
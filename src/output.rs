@@ -1,57 +1,184 @@
-use std::fs::File;
 use std::io::{self, Write};
-use std::vec::Vec;
-use crate::parser::PlayerStats;
+#[cfg(feature = "templates")]
+use crate::template;
+use crate::leaderboard::Movement;
+use crate::parser::{PlayerStats, TrafficStats};
+use crate::traffic::{Period, TrafficSummary};
 use chrono::Local;
+use serde::Serialize;
 
-pub fn write_json(players: &Vec<PlayerStats>, output_path: &str, pretty: bool) -> io::Result<()> {
-    let file = File::create(output_path)?;
-    if pretty {
-        serde_json::to_writer_pretty(file, players)?;
+/// Everything the writers need to render a leaderboard.
+pub struct Report<'a> {
+    pub title: &'a str,
+    pub players: &'a [PlayerStats],
+    pub uptime: usize,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub users: usize,
+    /// Players left out by the eligibility filters.
+    pub excluded: usize,
+    /// Rank of each player on the previous leaderboard, parallel to `players`.
+    /// When set, every writer adds rank movement to its rows.
+    pub previous_ranks: Option<&'a [Option<usize>]>,
+    /// Server-Sent Events URL the HTML page subscribes to for live table updates.
+    pub live: Option<&'a str>,
+    /// Hourly records the summary was built from, drawn as charts in HTML.
+    pub traffic: &'a [TrafficStats],
+    /// Whether the HTML page embeds the traffic and activity charts.
+    pub charts: bool,
+    /// Embed the stylesheet and sortable script instead of loading them from a CDN.
+    pub offline: bool,
+    /// Template source that `write_html` or `write_md` renders instead of the built-in layout.
+    pub template: Option<&'a str>,
+    /// Link players to their pages of a generated site instead of their Steam/USGN profiles.
+    pub profile_pages: bool,
+    /// Number of higher-ranked players on earlier pages; ranks start at `offset + 1`.
+    pub offset: usize,
+    /// Set when the leaderboard is split over several files.
+    pub pagination: Option<Pagination<'a>>,
+}
+
+/// The position of one page of a leaderboard that is split over several files.
+#[derive(Clone, Copy)]
+pub struct Pagination<'a> {
+    /// One-based number of this page.
+    pub page: usize,
+    /// Links to every page relative to this one, first page first.
+    pub links: &'a [String],
+}
+
+#[derive(Serialize)]
+struct RankedPlayer<'a> {
+    #[serde(flatten)]
+    player: &'a PlayerStats,
+    rank: usize,
+    previous_rank: Option<usize>,
+    rank_change: Option<i64>,
+}
+
+impl<'a> Report<'a> {
+    /// Builds a report for `players`, summarising uptime and bandwidth from `traffic`.
+    /// `users` is the number of ranked users before any limit was applied.
+    pub fn new(title: &'a str, players: &'a [PlayerStats], traffic: &'a [TrafficStats], users: usize) -> Self {
+        Report {
+            title,
+            players,
+            uptime: traffic.len(),
+            uploaded: traffic.iter().map(|t| t.uploaded_bytes as u64).sum(),
+            downloaded: traffic.iter().map(|t| t.downloaded_bytes as u64).sum(),
+            users,
+            excluded: 0,
+            previous_ranks: None,
+            live: None,
+            traffic,
+            charts: true,
+            offline: false,
+            template: None,
+            profile_pages: false,
+            offset: 0,
+            pagination: None,
+        }
+    }
+
+    /// Rank of the player at `index`.
+    pub fn rank(&self, index: usize) -> usize {
+        self.offset + index + 1
+    }
+
+    /// Rank movement of the player at `index`, if previous ranks are known.
+    pub fn movement(&self, index: usize) -> Option<Movement> {
+        let previous_ranks = self.previous_ranks?;
+        Some(Movement::new(self.rank(index), previous_ranks.get(index).copied().flatten()))
+    }
+}
+
+pub fn write_json<W: Write>(writer: W, report: &Report, pretty: bool) -> io::Result<()> {
+    if let Some(previous_ranks) = report.previous_ranks {
+        let players: Vec<RankedPlayer> = report
+            .players
+            .iter()
+            .enumerate()
+            .map(|(i, player)| RankedPlayer {
+                player,
+                rank: report.rank(i),
+                previous_rank: previous_ranks.get(i).copied().flatten(),
+                rank_change: report.movement(i).and_then(Movement::delta),
+            })
+            .collect();
+        if pretty {
+            serde_json::to_writer_pretty(writer, &players)?;
+        } else {
+            serde_json::to_writer(writer, &players)?;
+        }
+    } else if pretty {
+        serde_json::to_writer_pretty(writer, report.players)?;
     } else {
-        serde_json::to_writer(file, players)?;
+        serde_json::to_writer(writer, report.players)?;
     }
     Ok(())
 }
 
-pub fn write_csv(players: &Vec<PlayerStats>, output_path: &str) -> io::Result<()> {
-    let file = File::create(output_path)?;
-    let mut writer = csv::Writer::from_writer(file);
+pub fn write_csv<W: Write>(writer: W, report: &Report) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
 
-    writer.write_record(&[
+    let mut header = vec![
         "name", "usertype", "userid", "score", "kills", "deaths", "assists", "mvps", "time",
-    ])?;
-
-    for player in players {
-        writer.write_record(&[
-            &player.name,
-            &player.usertype.to_string(),
-            &player.userid.to_string(),
-            &player.score.to_string(),
-            &player.kills.to_string(),
-            &player.deaths.to_string(),
-            &player.assists.to_string(),
-            &player.mvps.to_string(),
-            &player.time.to_string(),
-        ])?;
+    ];
+    if report.previous_ranks.is_some() {
+        header.extend(["rank", "previous_rank", "rank_change"]);
+    }
+    writer.write_record(&header)?;
+
+    for (i, player) in report.players.iter().enumerate() {
+        let mut record = vec![
+            player.name.clone(),
+            player.usertype.to_string(),
+            player.userid.to_string(),
+            player.score.to_string(),
+            player.kills.to_string(),
+            player.deaths.to_string(),
+            player.assists.to_string(),
+            player.mvps.to_string(),
+            player.time.to_string(),
+        ];
+        if let Some(movement) = report.movement(i) {
+            let previous_rank = report.previous_ranks.and_then(|r| r[i]);
+            record.push(report.rank(i).to_string());
+            record.push(previous_rank.map(|r| r.to_string()).unwrap_or_default());
+            record.push(movement.delta().map(|d| d.to_string()).unwrap_or_default());
+        }
+        writer.write_record(&record)?;
     }
 
+    writer.flush()?;
     Ok(())
 }
 
-pub fn write_html(
-    players: &Vec<PlayerStats>,
-    output_path: &str,
-    title: &str,
-    uptime: usize,
-    uploaded: u64,
-    downloaded: u64,
-    users: usize,
-) -> io::Result<()> {
-    let mut file = File::create(output_path)?;
-    let formatted_time = Local::now().format("%d %b %Y at %H:%M:%S").to_string();
+/// Styles of the report pages on top of Pico CSS.
+const REPORT_CSS: &str = include_str!("../assets/report.css");
+/// Stand-in for the Pico CSS subset the pages use, embedded in offline pages.
+const OFFLINE_CSS: &str = include_str!("../assets/offline.css");
+/// Click-to-sort for `table.sortable`, embedded in offline pages.
+const SORTABLE_JS: &str = include_str!("../assets/sortable.js");
 
-    let mut html = format!(
+/// Favicon, Pico CSS and the report styles. Offline pages embed their
+/// stylesheets and use an empty favicon, so they load nothing.
+pub(crate) fn stylesheets(offline: bool) -> String {
+    if offline {
+        format!(r#"<link rel="icon" href="data:,"><style>{}</style><style>{}</style>"#, OFFLINE_CSS, REPORT_CSS)
+    } else {
+        format!(
+            r#"<link rel="icon" href="http://cs2d.com/favicon.ico" type="image/x-icon">
+            <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css">
+            <style>{}</style>"#,
+            REPORT_CSS
+        )
+    }
+}
+
+/// The document start shared by the HTML pages, up to and including `</head>`.
+fn html_head(title: &str, offline: bool) -> String {
+    format!(
         r#"<!DOCTYPE html>
         <html lang="en">
         <head>
@@ -59,68 +186,201 @@ pub fn write_html(
             <meta name="viewport" content="width=device-width, initial-scale=1">
             <meta name="color-scheme" content="light dark">
             <title>{}</title>
-            <link rel="icon" href="http://cs2d.com/favicon.ico" type="image/x-icon">
-            <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/@picocss/pico@2/css/pico.min.css">
-            <style>
-                * {{
-                    font-size: 100%;
-                }}
-                header {{
-                    margin-top: 1rem;
-                }}
-                article {{
-                    margin-bottom: 0;
-                }}
-                b {{
-                    font-weight: 600;
-                    display: block;
-                    font-size: 16px;
-                }}
-                tr, td {{
-                    white-space: nowrap;
-                }}
-                @media (min-width: 1280px), (min-width: 1536px) {{
-                    .container {{
-                        max-width: 950px;
-                    }}
-                }}
-                .sortable thead th:not(.no-sort) {{ cursor: pointer; }}
-                .sortable thead th:not(.no-sort)::after, .sortable thead th:not(.no-sort)::before {{
-                    transition: color 0.1s ease-in-out;
-                    vertical-align: baseline;
-                    color: transparent;
-                }}
-                .sortable thead th:not(.no-sort)::after {{ margin-left: 3px; content: "▸"; }}
-                .sortable thead th:not(.no-sort):hover::after {{ color: inherit; }}
-                .sortable thead th:not(.no-sort)[aria-sort=descending]::after {{
-                    color: inherit;
-                    content: "▾";
-                }}
-                .sortable thead th:not(.no-sort)[aria-sort=ascending]::after {{
-                    color: inherit;
-                    content: "▴";
-                }}
-                .sortable thead th:not(.no-sort).indicator-left::after {{ content: ""; }}
-                .sortable thead th:not(.no-sort).indicator-left::before {{
-                    margin-right: 3px;
-                    content: "▸";
-                }}
-                .sortable thead th:not(.no-sort).indicator-left:hover::before {{ color: inherit; }}
-                .sortable thead th:not(.no-sort).indicator-left[aria-sort=descending]::before {{
-                    color: inherit;
-                    content: "▾";
-                }}
-                .sortable thead th:not(.no-sort).indicator-left[aria-sort=ascending]::before {{
-                    color: inherit;
-                    content: "▴";
-                }}
-            </style>
+            {}
         </head>
-        <body class="container">
+"#,
+        title,
+        stylesheets(offline)
+    )
+}
+
+/// The script that makes `table.sortable` headers clickable.
+fn sortable_script(offline: bool) -> String {
+    if offline {
+        format!("<script>{}</script>", SORTABLE_JS)
+    } else {
+        r#"<script src="https://cdn.jsdelivr.net/gh/tofsjonas/sortable@latest/dist/sortable.min.js"></script>"#.to_string()
+    }
+}
+
+/// Renders the leaderboard with `report.template`, or the built-in layout.
+#[cfg(feature = "templates")]
+pub fn write_html<W: Write>(mut writer: W, report: &Report) -> io::Result<()> {
+    let html = match report.template {
+        Some(source) => template::render("template.html", source, report),
+        None => template::render("leaderboard.html", template::HTML, report).map(|html| minify(&html)),
+    };
+    write!(writer, "{}", html.map_err(io::Error::other)?)
+}
+
+/// The sortable script, plus the live update script when `live` is set.
+/// With `profile_pages`, live rows link to the player pages of a generated site.
+#[cfg(feature = "templates")]
+pub(crate) fn scripts(offline: bool, live: Option<&str>, profile_pages: bool) -> String {
+    let mut scripts = sortable_script(offline);
+    if let Some(url) = live {
+        let url = serde_json::Value::from(url).to_string().replace("</", "<\\/");
+        scripts.push_str(&LIVE_SCRIPT.replace("{url}", &url).replace("{profile_pages}", &profile_pages.to_string()));
+    }
+    scripts
+}
+
+/// Replaces the table rows with each `snapshot` event from the URL in `{url}`,
+/// linking players to their site pages when `{profile_pages}` is `true`.
+/// Rank movement is not part of the events, so each player keeps the movement
+/// cell the page was rendered with, found by the row's `data-player` key.
+/// Statements end with semicolons because the page is minified onto one line.
+#[cfg(feature = "templates")]
+const LIVE_SCRIPT: &str = r##"<script>
+(function () {
+    var source = new EventSource({url});
+    var duration = function (s) {
+        var d = Math.floor(s / 86400), h = Math.floor(s % 86400 / 3600), m = Math.floor(s % 3600 / 60);
+        if (d > 0) return d + "d " + h + "h";
+        if (h > 0) return h + "h " + m + "m";
+        if (m > 0) return m + "m " + s % 60 + "s";
+        return s % 60 + "s";
+    };
+    source.addEventListener("snapshot", function (event) {
+        var data = JSON.parse(event.data);
+        var movement = document.querySelector("th.movement") !== null;
+        var previous = {};
+        if (movement) document.querySelectorAll("#players tr[data-player]").forEach(function (row) {
+            previous[row.getAttribute("data-player")] = row.children[1];
+        });
+        var rows = data.players.map(function (p) {
+            var row = document.createElement("tr");
+            var key = p.usertype + ":" + p.userid;
+            row.setAttribute("data-player", key);
+            var cell = function (tag, text, sort) {
+                var c = document.createElement(tag);
+                c.textContent = text;
+                if (sort !== undefined) c.setAttribute("data-sort", sort);
+                row.appendChild(c);
+                return c;
+            };
+            cell("td", p.rank);
+            if (movement) row.appendChild(previous[key] || document.createElement("td"));
+            var link = document.createElement("a");
+            if ({profile_pages}) {
+                link.href = "players/" + (p.usertype === 1 ? "steam" : "usgn") + "/" + p.userid + ".html";
+            } else {
+                link.href = p.usertype === 1
+                    ? "https://steamcommunity.com/profiles/" + p.userid
+                    : "https://unrealsoftware.de/profile.php?userid=" + p.userid;
+                link.target = "_blank";
+            }
+            link.textContent = p.name;
+            var name = cell("th", "");
+            name.scope = "row";
+            name.appendChild(link);
+            cell("td", (p.deaths > 0 ? p.kills / p.deaths : p.kills).toFixed(2));
+            cell("td", p.kills);
+            cell("td", p.assists);
+            cell("td", p.deaths);
+            cell("td", p.mvps);
+            cell("td", duration(p.time), p.time);
+            return row;
+        });
+        var body = document.getElementById("players");
+        body.replaceChildren.apply(body, rows);
+        document.getElementById("users").textContent = data.total;
+        document.getElementById("updated").textContent = "Updated " + new Date(data.updated).toLocaleString();
+    });
+})();
+</script>"##;
+
+/// Renders the leaderboard with `report.template`, or the built-in layout.
+#[cfg(feature = "templates")]
+pub fn write_md<W: Write>(mut writer: W, report: &Report) -> io::Result<()> {
+    let markdown = match report.template {
+        Some(source) => template::render("template.md", source, report),
+        None => template::render("leaderboard.md", template::MARKDOWN, report),
+    };
+    write!(writer, "{}", markdown.map_err(io::Error::other)?)
+}
+
+pub fn write_xml<W: Write>(mut writer: W, report: &Report) -> io::Result<()> {
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<players>")?;
+
+    for (i, p) in report.players.iter().enumerate() {
+        writeln!(writer, "  <player>")?;
+        writeln!(writer, "    <name>{}</name>", sanitize_html(&p.name))?;
+        writeln!(writer, "    <usertype>{}</usertype>", p.usertype)?;
+        writeln!(writer, "    <userid>{}</userid>", p.userid)?;
+        writeln!(writer, "    <score>{}</score>", p.score)?;
+        writeln!(writer, "    <kills>{}</kills>", p.kills)?;
+        writeln!(writer, "    <assists>{}</assists>", p.assists)?;
+        writeln!(writer, "    <deaths>{}</deaths>", p.deaths)?;
+        writeln!(writer, "    <mvps>{}</mvps>", p.mvps)?;
+        writeln!(writer, "    <time>{}</time>", p.time)?;
+        if let Some(movement) = report.movement(i) {
+            writeln!(writer, "    <rank>{}</rank>", report.rank(i))?;
+            if let Some(previous_rank) = report.previous_ranks.and_then(|r| r[i]) {
+                writeln!(writer, "    <previous_rank>{}</previous_rank>", previous_rank)?;
+            }
+            if let Some(delta) = movement.delta() {
+                writeln!(writer, "    <rank_change>{}</rank_change>", delta)?;
+            }
+        }
+        writeln!(writer, "  </player>")?;
+    }
+
+    writeln!(writer, "</players>")?;
+    Ok(())
+}
+
+
+
+pub fn write_traffic_json<W: Write>(writer: W, summary: &TrafficSummary, pretty: bool) -> io::Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(writer, summary)?;
+    } else {
+        serde_json::to_writer(writer, summary)?;
+    }
+    Ok(())
+}
+
+/// Writes one row per period, with a `series` column of `day`, `month` or `hour`.
+pub fn write_traffic_csv<W: Write>(writer: W, summary: &TrafficSummary) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["series", "period", "hours", "uploaded", "downloaded", "average_players", "peak_players"])?;
+
+    for (series, periods) in traffic_series(summary) {
+        for period in periods {
+            writer.write_record([
+                series.to_string(),
+                period.label.clone(),
+                period.hours.to_string(),
+                period.uploaded.to_string(),
+                period.downloaded.to_string(),
+                format!("{:.2}", period.average_players),
+                period.peak_players.to_string(),
+            ])?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_traffic_html<W: Write>(
+    mut writer: W,
+    title: &str,
+    summary: &TrafficSummary,
+    offline: bool,
+) -> io::Result<()> {
+    let formatted_time = Local::now().format("%d %b %Y at %H:%M:%S").to_string();
+    let title = sanitize_html(title);
+
+    let mut html = html_head(&title, offline);
+    html.push_str(&format!(
+        r#"<body class="container">
             <header>
                 <hgroup>
                     <h1>{}</h1>
-                    <p>Updated {}</p>
+                    <p>Traffic updated {}</p>
                 </hgroup>
                 <div class="grid">
                     <article>
@@ -136,173 +396,184 @@ pub fn write_html(
                         <span>{}</span>
                     </article>
                     <article>
-                        <b>Total Traffic</b>
-                        <span>{}</span>
+                        <b>Average Players</b>
+                        <span>{:.2}</span>
                     </article>
                     <article>
-                        <b>Ranked Users</b>
-                        <span>{}</span>
+                        <b>Peak Players</b>
+                        <span>{}{}</span>
                     </article>
                 </div>
             </header>
-            <main>
-                <div class="overflow-auto">
-                    <table class="sortable striped">
-                        <thead>
-                            <tr>
-                                <th scope="col" aria-sort="ascending">#</th>
-                                <th scope="col">Player</th>
-                                <th scope="col">K/D</th>
-                                <th scope="col">K</th>
-                                <th scope="col">A</th>
-                                <th scope="col">D</th>
-                                <th scope="col">⭐</th>
-                                <th scope="col">⌚</th>
-                            </tr>
-                        </thead>
-                        <tbody>"#,
-        title, title, formatted_time, uptime, format_bytes(uploaded),
-        format_bytes(downloaded), format_bytes(uploaded + downloaded), users
-    );
-
-    for (i, p) in players.iter().enumerate() {
-        let profile_url = if p.usertype == 1 {
-            format!("https://steamcommunity.com/profiles/{}", p.userid)
-        } else {
-            format!("https://unrealsoftware.de/profile.php?userid={}", p.userid)
-        };
-
-        let kd_ratio = if p.deaths > 0 {
-            p.kills as f32 / p.deaths as f32
-        } else {
-            p.kills as f32
-        };
+            <main>"#,
+        title,
+        formatted_time,
+        summary.hours,
+        format_bytes(summary.uploaded),
+        format_bytes(summary.downloaded),
+        summary.average_players,
+        summary.peak_players,
+        summary.peak_at.as_ref().map(|at| format!(" ({})", at)).unwrap_or_default(),
+    ));
 
+    for (heading, periods) in [
+        ("Busiest Hours", &summary.busiest_hours),
+        ("Months", &summary.months),
+        ("Days", &summary.days),
+    ] {
         html.push_str(&format!(
-            r#"<tr>
-                <td>{}</td>
-                <th scope="row"><a href="{}" target="_blank">{}</a></th>
-                <td>{:.2}</td>
-                <td>{}</td>
-                <td>{}</td>
-                <td>{}</td>
-                <td>{}</td>
-                <td data-sort="{}">{}</td>
-            </tr>"#,
-            i + 1,
-            profile_url,
-            sanitize_html(&p.name),
-            kd_ratio,
-            p.kills,
-            p.assists,
-            p.deaths,
-            p.mvps,
-            p.time,
-            format_time(p.time.try_into().unwrap())
+            r#"<h2>{}</h2>
+            <div class="overflow-auto">
+                <table class="sortable striped">
+                    <thead>
+                        <tr>
+                            <th scope="col">Period</th>
+                            <th scope="col">Hours</th>
+                            <th scope="col">Upload</th>
+                            <th scope="col">Download</th>
+                            <th scope="col">Avg Players</th>
+                            <th scope="col">Peak Players</th>
+                        </tr>
+                    </thead>
+                    <tbody>"#,
+            heading
         ));
+        for period in periods {
+            html.push_str(&format!(
+                r#"<tr>
+                    <th scope="row">{}</th>
+                    <td>{}</td>
+                    <td data-sort="{}">{}</td>
+                    <td data-sort="{}">{}</td>
+                    <td>{:.2}</td>
+                    <td>{}</td>
+                </tr>"#,
+                period.label,
+                period.hours,
+                period.uploaded,
+                format_bytes(period.uploaded),
+                period.downloaded,
+                format_bytes(period.downloaded),
+                period.average_players,
+                period.peak_players
+            ));
+        }
+        html.push_str("</tbody></table></div>");
     }
 
-    html.push_str(r#"</tbody></table></div></main><script src="https://cdn.jsdelivr.net/gh/tofsjonas/sortable@latest/dist/sortable.min.js"></script></body></html>"#);
+    html.push_str("</main>");
+    html.push_str(&sortable_script(offline));
+    html.push_str("</body></html>");
+    write!(writer, "{}", minify(&html))?;
+
+    Ok(())
+}
+
+pub fn write_traffic_md<W: Write>(mut writer: W, title: &str, summary: &TrafficSummary) -> io::Result<()> {
+    let formatted_time = Local::now().format("%d %b %Y at %H:%M:%S").to_string();
+
+    writeln!(writer, "# {}", title)?;
+    writeln!(writer)?;
+    writeln!(writer, "- **Updated:** {}", formatted_time)?;
+    writeln!(writer, "- **Uptime:** ~{} h", summary.hours)?;
+    writeln!(writer, "- **Upload Traffic:** {}", format_bytes(summary.uploaded))?;
+    writeln!(writer, "- **Download Traffic:** {}", format_bytes(summary.downloaded))?;
+    writeln!(writer, "- **Average Players:** {:.2}", summary.average_players)?;
+    match &summary.peak_at {
+        Some(at) => writeln!(writer, "- **Peak Players:** {} ({})", summary.peak_players, at)?,
+        None => writeln!(writer, "- **Peak Players:** {}", summary.peak_players)?,
+    }
 
+    for (heading, periods) in [
+        ("Busiest Hours", &summary.busiest_hours),
+        ("Months", &summary.months),
+        ("Days", &summary.days),
+    ] {
+        writeln!(writer)?;
+        writeln!(writer, "## {}", heading)?;
+        writeln!(writer)?;
+        writeln!(writer, "| Period | Hours | Upload | Download | Avg Players | Peak Players |")?;
+        writeln!(writer, "|--------|-------|--------|----------|-------------|--------------|")?;
+        for period in periods {
+            writeln!(
+                writer,
+                "| {} | {} | {} | {} | {:.2} | {} |",
+                period.label,
+                period.hours,
+                format_bytes(period.uploaded),
+                format_bytes(period.downloaded),
+                period.average_players,
+                period.peak_players
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_traffic_xml<W: Write>(mut writer: W, summary: &TrafficSummary) -> io::Result<()> {
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<traffic>")?;
+    writeln!(writer, "  <hours>{}</hours>", summary.hours)?;
+    writeln!(writer, "  <uploaded>{}</uploaded>", summary.uploaded)?;
+    writeln!(writer, "  <downloaded>{}</downloaded>", summary.downloaded)?;
+    writeln!(writer, "  <average_players>{:.2}</average_players>", summary.average_players)?;
+    writeln!(writer, "  <peak_players>{}</peak_players>", summary.peak_players)?;
+    if let Some(at) = &summary.peak_at {
+        writeln!(writer, "  <peak_at>{}</peak_at>", at)?;
+    }
+
+    for (series, periods) in traffic_series(summary) {
+        writeln!(writer, "  <{}s>", series)?;
+        for period in periods {
+            writeln!(writer, "    <{}>", series)?;
+            writeln!(writer, "      <period>{}</period>", period.label)?;
+            writeln!(writer, "      <hours>{}</hours>", period.hours)?;
+            writeln!(writer, "      <uploaded>{}</uploaded>", period.uploaded)?;
+            writeln!(writer, "      <downloaded>{}</downloaded>", period.downloaded)?;
+            writeln!(writer, "      <average_players>{:.2}</average_players>", period.average_players)?;
+            writeln!(writer, "      <peak_players>{}</peak_players>", period.peak_players)?;
+            writeln!(writer, "    </{}>", series)?;
+        }
+        writeln!(writer, "  </{}s>", series)?;
+    }
+
+    writeln!(writer, "</traffic>")?;
+    Ok(())
+}
+
+/// The period series of `summary` with their singular names, in file order.
+fn traffic_series(summary: &TrafficSummary) -> [(&'static str, &[Period]); 3] {
+    [("day", &summary.days), ("month", &summary.months), ("hour", &summary.busiest_hours)]
+}
+
+/// Collapses whitespace runs to one space and drops it between tags.
+pub(crate) fn minify(html: &str) -> String {
     let mut minified_html = String::with_capacity(html.len());
     let mut last_was_space = false;
     let mut prev_char = '\0';
-    
+
     for c in html.chars() {
         if c.is_whitespace() {
             last_was_space = true;
             continue;
         }
-    
+
         if last_was_space {
             if !(prev_char == '>' && c == '<') {
                 minified_html.push(' ');
             }
             last_was_space = false;
         }
-    
+
         minified_html.push(c);
         prev_char = c;
     }
-    write!(file, "{}", minified_html.trim())?;
-    
-    Ok(())
-}
-
-pub fn write_md(
-    players: &Vec<PlayerStats>,
-    output_path: &str,
-    title: &str,
-    uptime: usize,
-    uploaded: u64,
-    downloaded: u64,
-    users: usize,
-) -> io::Result<()> {
-    let mut file = File::create(output_path)?;
-    let formatted_time = Local::now().format("%d %b %Y at %H:%M:%S").to_string();
-
-    writeln!(file, "# {}", title)?;
-    writeln!(file)?;
-    writeln!(file, "- **Updated:** {}", formatted_time)?;
-    writeln!(file, "- **Uptime:** ~{} h", uptime)?;
-    writeln!(file, "- **Upload Traffic:** {}", format_bytes(uploaded))?;
-    writeln!(file, "- **Download Traffic:** {}", format_bytes(downloaded))?;
-    writeln!(file, "- **Total Traffic:** {}", format_bytes(uploaded + downloaded))?;
-    writeln!(file, "- **Ranked Users:** {}", users)?;
-    writeln!(file)?;
-    writeln!(file, "| # | Player | K/D | K | A | D | ⭐ | ⌚ |")?;
-    writeln!(file, "|---|--------|-----|---|---|---|----|----|")?;
-
-    for (i, p) in players.iter().enumerate() {
-        let kd_ratio = if p.deaths > 0 {
-            p.kills as f32 / p.deaths as f32
-        } else {
-            p.kills as f32 // To avoid division by zero, assume infinite K/D if deaths are 0
-        };
-
-        writeln!(
-            file,
-            "| {} | {} | {:.2} | {} | {} | {} | {} | {} |",
-            i + 1,
-            p.name,
-            kd_ratio,
-            p.kills,
-            p.assists,
-            p.deaths,
-            p.mvps,
-            format_time(p.time.try_into().unwrap())
-        )?;
-    }
-
-    Ok(())
+    minified_html.trim().to_string()
 }
 
-pub fn write_xml(players: &Vec<PlayerStats>, output_path: &str) -> io::Result<()> {
-    let mut file = File::create(output_path)?;
-    writeln!(file, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
-    writeln!(file, "<players>")?;
-
-    for p in players {
-        writeln!(file, "  <player>")?;
-        writeln!(file, "    <name>{}</name>", sanitize_html(&p.name))?;
-        writeln!(file, "    <usertype>{}</usertype>", p.usertype)?;
-        writeln!(file, "    <userid>{}</userid>", p.userid)?;
-        writeln!(file, "    <score>{}</score>", p.score)?;
-        writeln!(file, "    <kills>{}</kills>", p.kills)?;
-        writeln!(file, "    <assists>{}</assists>", p.assists)?;
-        writeln!(file, "    <deaths>{}</deaths>", p.deaths)?;
-        writeln!(file, "    <mvps>{}</mvps>", p.mvps)?;
-        writeln!(file, "    <time>{}</time>", p.time)?;
-        writeln!(file, "  </player>")?;
-    }
-
-    writeln!(file, "</players>")?;
-    Ok(())
-}
-
-
-
-fn format_time(s: i32) -> String {
+#[cfg(feature = "templates")]
+pub(crate) fn format_time(s: i32) -> String {
     let days = s / 86400;
     let hours = (s % 86400) / 3600;
     let minutes = (s % 3600) / 60;
@@ -319,7 +590,7 @@ fn format_time(s: i32) -> String {
     }
 }
 
-fn format_bytes(bytes: u64) -> String {
+pub(crate) fn format_bytes(bytes: u64) -> String {
     let kb = 1024.0;
     let mb = kb * 1024.0;
     let gb = mb * 1024.0;
@@ -344,8 +615,7 @@ fn sanitize_html(input: &str) -> String {
         .replace('>', "&gt;")
         .replace('"', "&quot;")
         .replace('\'', "&#x27;")
-        .replace('\n', " ")
-        .replace('\r', " ")
+        .replace(['\n', '\r'], " ")
         .trim()
         .to_string()
 }
@@ -0,0 +1,76 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::output::{self, Pagination, Report};
+use crate::parser::PlayerStats;
+use crate::publish;
+
+/// The formats a leaderboard can be split into pages in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageFormat {
+    Html,
+    Markdown,
+}
+
+impl PageFormat {
+    /// The file extension of later pages, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            PageFormat::Html => "html",
+            PageFormat::Markdown => "md",
+        }
+    }
+}
+
+/// Writes the leaderboard of `report` as pages of `page_size` rows: `path`
+/// itself, then `name-2.ext`, `name-3.ext`, ... next to it.
+///
+/// Every page links to all the others, and the live updates of each page only
+/// fetch the rows shown on it. With `keep`, that many previous versions of every
+/// page are kept as backups. The page count is recorded in `.name.ext.pages` so
+/// that the pages of an earlier, longer run can be deleted.
+pub fn write_pages(path: &Path, report: &Report, format: PageFormat, page_size: usize, keep: Option<usize>) -> io::Result<()> {
+    let ext = format.extension();
+    let mut chunks: Vec<&[PlayerStats]> = report.players.chunks(page_size).collect();
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let links: Vec<String> = (1..=chunks.len())
+        .map(|n| match n {
+            1 => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            n => format!("{}-{}.{}", stem, n, ext),
+        })
+        .collect();
+
+    for (i, players) in chunks.into_iter().enumerate() {
+        let offset = i * page_size;
+        let live = report.live.map(|url| {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            format!("{}{}offset={}&limit={}", url, separator, offset, page_size)
+        });
+        let page = Report {
+            players,
+            offset,
+            previous_ranks: report.previous_ranks.map(|ranks| &ranks[offset..offset + players.len()]),
+            live: live.as_deref(),
+            pagination: Some(Pagination { page: i + 1, links: &links }),
+            ..*report
+        };
+
+        publish::replace_keeping(&path.with_file_name(&links[i]), keep, |writer| match format {
+            PageFormat::Html => output::write_html(writer, &page),
+            PageFormat::Markdown => output::write_md(writer, &page),
+        })?;
+    }
+
+    // Pages beyond the last one, left by an earlier run with more players. Only
+    // the count this function recorded is trusted, so other files are never touched.
+    let count_path = path.with_file_name(format!(".{}.pages", path.file_name().unwrap_or_default().to_string_lossy()));
+    let previous: usize = fs::read_to_string(&count_path).ok().and_then(|n| n.trim().parse().ok()).unwrap_or(0);
+    for n in links.len() + 1..=previous {
+        publish::remove(&path.with_file_name(format!("{}-{}.{}", stem, n, ext)), keep)?;
+    }
+    publish::replace(&count_path, |writer| writeln!(writer, "{}", links.len()))
+}
@@ -1,11 +1,19 @@
 use byteorder::{LittleEndian, ReadBytesExt};
-use serde::Serialize;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read};
+use std::io::{self, BufReader, Read};
+use crate::error::ParseError;
+use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Debug, Serialize)]
+/// A single player record from `userstats.dat`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PlayerStats {
     pub name: String,
+    /// The name exactly as stored in the file, before decoding.
+    #[serde(skip)]
+    pub raw_name: Vec<u8>,
     pub usertype: u8,
     pub userid: i64,
     pub score: i32,
@@ -16,7 +24,38 @@ pub struct PlayerStats {
     pub time: i32,
 }
 
-#[derive(Debug, Serialize)]
+impl PlayerStats {
+    /// The `(usertype, userid)` pair that identifies a player across files.
+    pub fn key(&self) -> (u8, i64) {
+        (self.usertype, self.userid)
+    }
+
+    /// Kills per death; players without deaths get their kill count.
+    pub fn kd_ratio(&self) -> f32 {
+        if self.deaths > 0 {
+            self.kills as f32 / self.deaths as f32
+        } else {
+            self.kills as f32
+        }
+    }
+}
+
+/// Parses a usertype given as `usgn`/`0` or `steam`/`1`.
+pub fn parse_usertype(s: &str) -> Option<u8> {
+    match s.to_ascii_lowercase().as_str() {
+        "0" | "usgn" => Some(0),
+        "1" | "steam" => Some(1),
+        _ => None,
+    }
+}
+
+/// The name of a usertype as accepted by [`parse_usertype`]: `steam` for 1, else `usgn`.
+pub fn usertype_name(usertype: u8) -> &'static str {
+    if usertype == 1 { "steam" } else { "usgn" }
+}
+
+/// An hourly traffic record from `stats.dat`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TrafficStats {
     pub day: u8,
     pub month: u8,
@@ -27,53 +66,203 @@ pub struct TrafficStats {
     pub players: u8,
 }
 
-fn read_header<R: BufRead>(reader: &mut R) -> io::Result<String> {
-    let mut header = String::new()
-    ;reader.read_line(&mut header)?;
-    Ok(header.trim_end().to_string())
+impl TrafficStats {
+    /// The start of the hour this record covers, or `None` if the date is invalid.
+    pub fn datetime(&self) -> Option<NaiveDateTime> {
+        chrono::NaiveDate::from_ymd_opt(self.year.into(), self.month.into(), self.day.into())?
+            .and_hms_opt(self.hour.into(), 0, 0)
+    }
 }
 
-fn read_line<R: Read>(reader: &mut BufReader<R>) -> io::Result<String> {
-    let mut line = String::new();
-    let mut byte = [0u8];
-    loop {
-        reader.read_exact(&mut byte)?;
-        if byte[0] == b'\n' {
-            break;
+/// Fallback encoding for player names that are not valid UTF-8.
+///
+/// CS2D stores names as raw bytes, so clients on older systems may have
+/// written them in a legacy code page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Map every byte to the Unicode code point of the same value.
+    #[default]
+    Latin1,
+    /// Cyrillic Windows code page.
+    Windows1251,
+    /// Replace invalid sequences with U+FFFD.
+    Lossy,
+}
+
+impl Encoding {
+    /// Decodes `bytes` as UTF-8, falling back to this encoding if that fails.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        if let Ok(name) = std::str::from_utf8(bytes) {
+            return name.to_string();
         }
-        if byte[0] != b'\r' {
-            line.push(byte[0] as char);
+        match self {
+            Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+            Encoding::Windows1251 => encoding_rs::WINDOWS_1251
+                .decode_without_bom_handling(bytes)
+                .0
+                .into_owned(),
+            Encoding::Lossy => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "cp1251" | "windows-1251" => Ok(Encoding::Windows1251),
+            "lossy" | "utf8" | "utf-8" => Ok(Encoding::Lossy),
+            _ => Err(format!("unknown encoding '{}' (expected latin1, cp1251 or lossy)", s)),
         }
     }
-    Ok(line)
 }
 
-pub fn parse_userstats(file_path: &str) -> io::Result<Vec<PlayerStats>> {
-    let file = File::open(file_path)?;
-    let mut reader = BufReader::new(file);
+pub(crate) const USERSTATS_HEADER: &str = "userstats steam";
+pub(crate) const STATS_HEADER: &str = "stats alpha";
+
+/// Wraps a reader and tracks the current byte offset and record index so
+/// errors can point at the exact spot in the file.
+struct RecordReader<R> {
+    inner: BufReader<R>,
+    offset: u64,
+    record: usize,
+}
+
+impl<R: Read> Read for RecordReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> RecordReader<R> {
+    fn new(reader: R) -> Self {
+        RecordReader { inner: BufReader::new(reader), offset: 0, record: 0 }
+    }
+
+    fn read_header(&mut self, expected: &'static str) -> Result<(), ParseError> {
+        let header = self.read_line()?.unwrap_or_default();
+        let header = String::from_utf8_lossy(&header);
+        if header.trim_end() != expected {
+            return Err(ParseError::BadHeader { expected, found: header.trim_end().to_string() });
+        }
+        Ok(())
+    }
+
+    /// Reads bytes up to the next newline, without the line ending.
+    /// Returns `None` at a clean end of file.
+    fn read_line(&mut self) -> Result<Option<Vec<u8>>, ParseError> {
+        let mut line = Vec::new();
+        let mut byte = [0u8];
+        loop {
+            match self.read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && line.is_empty() => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(self.truncated(self.offset - line.len() as u64, "name"));
+                }
+                Err(e) => return Err(e.into()),
+            }
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    /// Reads one fixed-size field, reporting a truncated record if the file ends early.
+    fn field<T>(
+        &mut self,
+        field: &'static str,
+        read: impl FnOnce(&mut Self) -> io::Result<T>,
+    ) -> Result<T, ParseError> {
+        let offset = self.offset;
+        read(self).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => self.truncated(offset, field),
+            _ => e.into(),
+        })
+    }
 
-    let header = read_header(&mut reader)?;
-    if header != "userstats steam" {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid header: expected 'userstats steam'"));
+    /// Like [`RecordReader::field`], but also rejects values outside `valid`.
+    fn checked_field<T: Copy + Into<i64>>(
+        &mut self,
+        field: &'static str,
+        valid: impl FnOnce(T) -> bool,
+        read: impl FnOnce(&mut Self) -> io::Result<T>,
+    ) -> Result<T, ParseError> {
+        let offset = self.offset;
+        let value = self.field(field, read)?;
+        if !valid(value) {
+            return Err(ParseError::InvalidField { offset, record: self.record, field, value: value.into() });
+        }
+        Ok(value)
     }
 
+    fn truncated(&self, offset: u64, field: &'static str) -> ParseError {
+        ParseError::TruncatedRecord { offset, record: self.record, field }
+    }
+
+    /// Fails if anything other than whitespace is left in the file.
+    fn expect_end(&mut self) -> Result<(), ParseError> {
+        let offset = self.offset;
+        let mut rest = Vec::new();
+        self.read_to_end(&mut rest)?;
+        if rest.trim_ascii().is_empty() {
+            return Ok(());
+        }
+        Err(ParseError::TrailingGarbage { offset, record: self.record, len: rest.len() as u64 })
+    }
+}
+
+/// Parses the `userstats.dat` file at `path`, decoding non-UTF-8 names as Latin-1.
+pub fn parse_userstats<P: AsRef<Path>>(path: P) -> Result<Vec<PlayerStats>, ParseError> {
+    parse_userstats_with(path, Encoding::default())
+}
+
+/// Parses the `userstats.dat` file at `path` with a custom fallback name encoding.
+pub fn parse_userstats_with<P: AsRef<Path>>(path: P, encoding: Encoding) -> Result<Vec<PlayerStats>, ParseError> {
+    read_userstats_with(File::open(path)?, encoding)
+}
+
+/// Parses `userstats.dat` contents from any reader.
+pub fn read_userstats<R: Read>(reader: R) -> Result<Vec<PlayerStats>, ParseError> {
+    read_userstats_with(reader, Encoding::default())
+}
+
+/// Parses `userstats.dat` contents from any reader with a custom fallback name encoding.
+///
+/// Records run until the end of the file or an empty name line; anything
+/// after an empty name line is reported as [`ParseError::TrailingGarbage`].
+pub fn read_userstats_with<R: Read>(reader: R, encoding: Encoding) -> Result<Vec<PlayerStats>, ParseError> {
+    let mut reader = RecordReader::new(reader);
+    reader.read_header(USERSTATS_HEADER)?;
+
     let mut players = Vec::new();
-    while let Ok(name) = read_line(&mut reader) {
-        if name.trim().is_empty() {
+    while let Some(raw_name) = reader.read_line()? {
+        if raw_name.trim_ascii().is_empty() {
+            reader.expect_end()?;
             break;
         }
 
-        let usertype = reader.read_u8()?;
-        let userid = reader.read_i64::<LittleEndian>()?;
-        let score = reader.read_i32::<LittleEndian>()?;
-        let kills = reader.read_i32::<LittleEndian>()?;
-        let deaths = reader.read_i32::<LittleEndian>()?;
-        let assists = reader.read_i32::<LittleEndian>()?;
-        let mvps = reader.read_i32::<LittleEndian>()?;
-        let time = reader.read_i32::<LittleEndian>()?;
+        let usertype = reader.checked_field("usertype", |t| t <= 1, |r| r.read_u8())?;
+        let userid = reader.field("userid", |r| r.read_i64::<LittleEndian>())?;
+        let score = reader.field("score", |r| r.read_i32::<LittleEndian>())?;
+        let kills = reader.field("kills", |r| r.read_i32::<LittleEndian>())?;
+        let deaths = reader.field("deaths", |r| r.read_i32::<LittleEndian>())?;
+        let assists = reader.field("assists", |r| r.read_i32::<LittleEndian>())?;
+        let mvps = reader.field("mvps", |r| r.read_i32::<LittleEndian>())?;
+        let time = reader.checked_field("time", |t| t >= 0, |r| r.read_i32::<LittleEndian>())?;
 
         players.push(PlayerStats {
-            name,
+            name: encoding.decode(&raw_name),
+            raw_name,
             usertype,
             userid,
             score,
@@ -83,34 +272,40 @@ pub fn parse_userstats(file_path: &str) -> io::Result<Vec<PlayerStats>> {
             mvps,
             time,
         });
+        reader.record += 1;
     }
 
     Ok(players)
 }
 
-pub fn parse_stats(file_path: &str) -> io::Result<Vec<TrafficStats>> {
-    let file = File::open(file_path)?;
-    let mut reader = BufReader::new(file);
+/// Parses the `stats.dat` file at `path`.
+pub fn parse_stats<P: AsRef<Path>>(path: P) -> Result<Vec<TrafficStats>, ParseError> {
+    read_stats(File::open(path)?)
+}
 
-    let header = read_header(&mut reader)?;
-    if header != "stats alpha" {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid header: expected 'stats alpha'"));
-    }
+/// Parses `stats.dat` contents from any reader.
+pub fn read_stats<R: Read>(reader: R) -> Result<Vec<TrafficStats>, ParseError> {
+    let mut reader = RecordReader::new(reader);
+    reader.read_header(STATS_HEADER)?;
 
     let mut traffic = Vec::new();
     loop {
+        let offset = reader.offset;
         let day = match reader.read_u8() {
             Ok(d) => d,
             Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
-            Err(e) => return Err(e),
+            Err(e) => return Err(e.into()),
         };
+        if !(1..=31).contains(&day) {
+            return Err(ParseError::InvalidField { offset, record: reader.record, field: "day", value: day.into() });
+        }
 
-        let month = reader.read_u8()?;
-        let year = reader.read_u16::<LittleEndian>()?;
-        let hour = reader.read_u8()?;
-        let uploaded_bytes = reader.read_i64::<LittleEndian>()?;
-        let downloaded_bytes = reader.read_i64::<LittleEndian>()?;
-        let players = reader.read_u8()?;
+        let month = reader.checked_field("month", |m| (1..=12).contains(&m), |r| r.read_u8())?;
+        let year = reader.field("year", |r| r.read_u16::<LittleEndian>())?;
+        let hour = reader.checked_field("hour", |h| h <= 23, |r| r.read_u8())?;
+        let uploaded_bytes = reader.field("uploaded_bytes", |r| r.read_i64::<LittleEndian>())?;
+        let downloaded_bytes = reader.field("downloaded_bytes", |r| r.read_i64::<LittleEndian>())?;
+        let players = reader.field("players", |r| r.read_u8())?;
 
         traffic.push(TrafficStats {
             day,
@@ -121,6 +316,7 @@ pub fn parse_stats(file_path: &str) -> io::Result<Vec<TrafficStats>> {
             downloaded_bytes,
             players,
         });
+        reader.record += 1;
     }
 
     Ok(traffic)
@@ -0,0 +1,135 @@
+use chrono::Local;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const STAMP_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// Replaces the file at `path` with what `write` writes.
+///
+/// The data goes to a temporary file in the same folder that is renamed over
+/// `path` only once everything was written, so readers such as a web server see
+/// either the old or the new file. If writing fails, the old file is left as it was.
+/// The new file keeps the permissions, and where allowed the owner, of the old one.
+pub fn replace<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let temp = path.with_file_name(format!(".{}.{}.tmp", file_name.to_string_lossy(), process::id()));
+
+    let result = File::create(&temp).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temp, metadata.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::{MetadataExt, chown};
+                // Only root may hand a file to another user; anyone else keeps their own.
+                let _ = chown(&temp, Some(metadata.uid()), Some(metadata.gid()));
+            }
+        }
+        fs::rename(&temp, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Like [`replace`], but first backs up an existing file at `path` with
+/// [`backup`], keeping `keep` versions, when `keep` is set.
+pub fn replace_keeping<F>(path: &Path, keep: Option<usize>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    if let Some(keep) = keep
+        && path.exists()
+    {
+        backup(path, Some(keep))?;
+    }
+    replace(path, write)
+}
+
+/// Deletes the published file at `path`, if it exists. With `keep`, it is backed
+/// up first like a replaced version; without, its backups are deleted too, so
+/// nothing of it is left behind.
+pub fn remove(path: &Path, keep: Option<usize>) -> io::Result<()> {
+    match keep {
+        Some(keep) if path.exists() => {
+            backup(path, Some(keep))?;
+        }
+        Some(_) => {}
+        None => {
+            for old in backups(path)? {
+                fs::remove_file(old)?;
+            }
+        }
+    }
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Copies `path` to a timestamped backup next to it, `name.YYYYmmdd-HHMMSS.mmm.bak`,
+/// then deletes the oldest backups of `path` beyond `keep`, if set.
+/// Returns the path of the new backup.
+pub fn backup(path: &Path, keep: Option<usize>) -> io::Result<PathBuf> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let stamp = Local::now().format(STAMP_FORMAT);
+    let mut backup = path.with_file_name(format!("{}.{}.bak", file_name, stamp));
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{}.{}-{}.bak", file_name, stamp, n));
+        n += 1;
+    }
+    fs::copy(path, &backup)?;
+
+    if let Some(keep) = keep {
+        let mut backups = backups(path)?;
+        let excess = backups.len().saturating_sub(keep);
+        for old in backups.drain(..excess) {
+            fs::remove_file(old)?;
+        }
+    }
+    Ok(backup)
+}
+
+/// The backups of `path` made by [`backup`], oldest first.
+pub fn backups(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut backups: Vec<((String, u32), PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(stamp) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(file_name)?.strip_prefix('.')?.strip_suffix(".bak"))
+        else {
+            continue;
+        };
+        if !stamp.starts_with(|c: char| c.is_ascii_digit()) || !stamp.chars().all(|c| c.is_ascii_digit() || c == '-' || c == '.') {
+            continue;
+        }
+        // `20240501-120000.000-1` was made after `20240501-120000.000`.
+        let order = match stamp.rsplit_once('-') {
+            Some((base, n)) if base.contains('-') => (base.to_string(), n.parse().unwrap_or(0)),
+            _ => (stamp.to_string(), 0),
+        };
+        backups.push((order, path.with_file_name(&name)));
+    }
+    backups.sort();
+    Ok(backups.into_iter().map(|(_, path)| path).collect())
+}
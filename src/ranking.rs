@@ -0,0 +1,492 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use crate::parser::PlayerStats;
+
+/// Fields and derived metrics that can be used in a sort expression.
+pub const FIELDS: &[&str] = &[
+    "score", "kills", "deaths", "assists", "mvps", "time", "kd", "kpm", "spm", "hours",
+];
+
+/// Functions that can be used in a sort expression.
+pub const FUNCTIONS: &[&str] = &["max", "min", "abs", "sqrt"];
+
+/// The built-in orders selectable as `--sort 0`, `1` and `2`.
+const PRESETS: [&str; 3] = ["score + kills - deaths", "assists + kills - deaths", "score + assists + deaths"];
+
+/// A leaderboard order: one or more expressions over [`PlayerStats`] fields,
+/// each ascending or descending, where later keys break ties of earlier ones.
+///
+/// The syntax is a comma-separated list of `expression [asc|desc]`, e.g.
+/// `kills/max(deaths,1) desc, time asc`. Keys are descending by default and
+/// `0`, `1` and `2` select the built-in orders. Division by zero gives `0`,
+/// unlike the `kd` field, which is `kills` when `deaths` is `0`.
+#[derive(Debug, Clone)]
+pub struct SortSpec {
+    source: String,
+    keys: Vec<SortKey>,
+}
+
+#[derive(Debug, Clone)]
+struct SortKey {
+    expr: Expr,
+    descending: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    Field(&'static str),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(&'static str, Vec<Expr>),
+}
+
+/// A syntax error in a sort expression, pointing at the offending characters.
+#[derive(Debug, Clone)]
+pub struct SortError {
+    pub source: String,
+    pub position: usize,
+    pub len: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = self.source[..self.position].chars().count();
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "  {}", self.source)?;
+        write!(f, "  {}{}", " ".repeat(column), "^".repeat(self.len.max(1)))
+    }
+}
+
+impl std::error::Error for SortError {}
+
+impl SortSpec {
+    /// Evaluates every sort key for `player`.
+    fn evaluate(&self, player: &PlayerStats) -> Vec<f64> {
+        self.keys.iter().map(|key| key.expr.eval(player)).collect()
+    }
+
+    /// Orders two players according to this spec, best first.
+    pub fn compare(&self, a: &PlayerStats, b: &PlayerStats) -> Ordering {
+        Self::compare_values(&self.keys, &self.evaluate(a), &self.evaluate(b))
+    }
+
+    fn compare_values(keys: &[SortKey], a: &[f64], b: &[f64]) -> Ordering {
+        keys.iter()
+            .zip(a.iter().zip(b))
+            .map(|(key, (a, b))| if key.descending { b.total_cmp(a) } else { a.total_cmp(b) })
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// Sorts `players` best first. Players that tie on every key keep their file order.
+    pub fn sort(&self, players: &mut Vec<PlayerStats>) {
+        let mut keyed: Vec<(Vec<f64>, PlayerStats)> = players.drain(..).map(|p| (self.evaluate(&p), p)).collect();
+        keyed.sort_by(|(a, _), (b, _)| Self::compare_values(&self.keys, a, b));
+        players.extend(keyed.into_iter().map(|(_, p)| p));
+    }
+}
+
+impl fmt::Display for SortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Default for SortSpec {
+    fn default() -> Self {
+        "1".parse().unwrap()
+    }
+}
+
+impl FromStr for SortSpec {
+    type Err = SortError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let source = match s.trim() {
+            "0" => PRESETS[0],
+            "1" => PRESETS[1],
+            "2" => PRESETS[2],
+            other => other,
+        };
+        let mut parser = Parser { source, tokens: tokenize(source)?, pos: 0 };
+
+        let mut keys = vec![parser.key()?];
+        while parser.eat(&Token::Comma) {
+            keys.push(parser.key()?);
+        }
+        if let Some((_, start, len)) = parser.tokens.get(parser.pos) {
+            return Err(error(source, *start, *len, "expected ',' or the end of the expression"));
+        }
+
+        Ok(SortSpec { source: s.trim().to_string(), keys })
+    }
+}
+
+impl Expr {
+    fn uses_field(&self) -> bool {
+        match self {
+            Expr::Number(_) => false,
+            Expr::Field(_) => true,
+            Expr::Neg(e) => e.uses_field(),
+            Expr::Binary(_, a, b) => a.uses_field() || b.uses_field(),
+            Expr::Call(_, args) => args.iter().any(Expr::uses_field),
+        }
+    }
+
+    fn eval(&self, p: &PlayerStats) -> f64 {
+        let value = match self {
+            Expr::Number(n) => *n,
+            Expr::Field(name) => field(p, name),
+            Expr::Neg(e) => -e.eval(p),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(p), b.eval(p));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    _ if b == 0.0 => 0.0,
+                    _ => a / b,
+                }
+            }
+            Expr::Call(name, args) => {
+                let args: Vec<f64> = args.iter().map(|a| a.eval(p)).collect();
+                match *name {
+                    "max" => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    "min" => args.iter().copied().fold(f64::INFINITY, f64::min),
+                    "abs" => args[0].abs(),
+                    _ => args[0].sqrt(),
+                }
+            }
+        };
+        // Keep the order total: anything undefined ranks like zero.
+        if value.is_finite() { value } else { 0.0 }
+    }
+}
+
+pub(crate) fn field(p: &PlayerStats, name: &str) -> f64 {
+    let minutes = p.time as f64 / 60.0;
+    let per_minute = |n: i32| if minutes > 0.0 { n as f64 / minutes } else { 0.0 };
+    match name {
+        "score" => p.score.into(),
+        "kills" => p.kills.into(),
+        "deaths" => p.deaths.into(),
+        "assists" => p.assists.into(),
+        "mvps" => p.mvps.into(),
+        "time" => p.time.into(),
+        "kd" => p.kd_ratio().into(),
+        "kpm" => per_minute(p.kills),
+        "spm" => per_minute(p.score),
+        "hours" => p.time as f64 / 3600.0,
+        _ => unreachable!(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn error(source: &str, position: usize, len: usize, message: impl Into<String>) -> SortError {
+    SortError { source: source.to_string(), position, len, message: message.into() }
+}
+
+/// Splits `source` into tokens with their byte offset and length.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize, usize)>, SortError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        // Extends a number or identifier token while `accept` matches.
+        let mut take_while = |accept: fn(char) -> bool| {
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if !accept(c) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            end
+        };
+
+        let (token, end) = if c.is_ascii_digit() || c == '.' {
+            let end = take_while(|c| c.is_ascii_digit() || c == '.');
+            let text = &source[start..end];
+            let n = text
+                .parse()
+                .map_err(|_| error(source, start, text.len(), format!("invalid number '{}'", text)))?;
+            (Token::Number(n), end)
+        } else if c.is_alphabetic() || c == '_' {
+            let end = take_while(|c| c.is_alphanumeric() || c == '_');
+            (Token::Ident(source[start..end].to_ascii_lowercase()), end)
+        } else {
+            let token = match c {
+                '+' | '-' | '*' | '/' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                _ => return Err(error(source, start, c.len_utf8(), format!("unexpected character '{}'", c))),
+            };
+            (token, start + c.len_utf8())
+        };
+        tokens.push((token, start, end - start));
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over the token list.
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _, _)| t)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Builds an error at the current token, or at the end of the input.
+    fn error_here(&self, message: impl Into<String>) -> SortError {
+        match self.tokens.get(self.pos) {
+            Some((_, start, len)) => error(self.source, *start, *len, message),
+            None => error(self.source, self.source.len(), 1, message),
+        }
+    }
+
+    fn key(&mut self) -> Result<SortKey, SortError> {
+        let start = self.tokens.get(self.pos).map_or(self.source.len(), |(_, start, _)| *start);
+        let expr = self.expr()?;
+        if !expr.uses_field() {
+            let (_, last, len) = self.tokens[self.pos - 1];
+            return Err(error(
+                self.source,
+                start,
+                last + len - start,
+                format!("the sort key uses no field, so every player would tie (available: {})", FIELDS.join(", ")),
+            ));
+        }
+        let descending = match self.peek() {
+            Some(Token::Ident(word)) if word == "asc" => false,
+            Some(Token::Ident(word)) if word == "desc" => true,
+            _ => return Ok(SortKey { expr, descending: true }),
+        };
+        self.pos += 1;
+        Ok(SortKey { expr, descending })
+    }
+
+    fn expr(&mut self) -> Result<Expr, SortError> {
+        let mut left = self.term()?;
+        while let Some(&Token::Op(op @ ('+' | '-'))) = self.peek() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, SortError> {
+        let mut left = self.unary()?;
+        while let Some(&Token::Op(op @ ('*' | '/'))) = self.peek() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, SortError> {
+        if self.eat(&Token::Op('-')) {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, SortError> {
+        let Some((token, start, len)) = self.tokens.get(self.pos).cloned() else {
+            return Err(self.error_here("expected a number, field or '(' but the expression ended"));
+        };
+
+        match token {
+            Token::Number(n) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Token::LParen => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                if !self.eat(&Token::RParen) {
+                    return Err(self.error_here("expected ')'"));
+                }
+                Ok(expr)
+            }
+            Token::Ident(name) if self.tokens.get(self.pos + 1).map(|(t, _, _)| t) == Some(&Token::LParen) => {
+                let Some(&function) = FUNCTIONS.iter().find(|f| **f == name) else {
+                    return Err(error(
+                        self.source,
+                        start,
+                        len,
+                        format!("unknown function '{}' (available: {})", name, FUNCTIONS.join(", ")),
+                    ));
+                };
+                self.pos += 2;
+
+                let mut args = vec![self.expr()?];
+                while self.eat(&Token::Comma) {
+                    args.push(self.expr()?);
+                }
+                if !self.eat(&Token::RParen) {
+                    return Err(self.error_here("expected ',' or ')' in the function arguments"));
+                }
+
+                let unary = matches!(function, "abs" | "sqrt");
+                if unary && args.len() != 1 {
+                    return Err(error(self.source, start, len, format!("{}() takes exactly one argument", function)));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            Token::Ident(name) => match FIELDS.iter().find(|f| **f == name) {
+                Some(&field) => {
+                    self.pos += 1;
+                    Ok(Expr::Field(field))
+                }
+                None => Err(error(
+                    self.source,
+                    start,
+                    len,
+                    format!("unknown field '{}' (available: {})", name, FIELDS.join(", ")),
+                )),
+            },
+            _ => Err(self.error_here("expected a number, field or '('")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(userid: i64, [score, kills, deaths, assists, mvps, time]: [i32; 6]) -> PlayerStats {
+        PlayerStats { name: userid.to_string(), raw_name: Vec::new(), usertype: 0, userid, score, kills, deaths, assists, mvps, time }
+    }
+
+    fn eval(source: &str, p: &PlayerStats) -> f64 {
+        source.parse::<SortSpec>().unwrap().evaluate(p)[0]
+    }
+
+    fn order(spec: &str, players: &[PlayerStats]) -> Vec<i64> {
+        let mut players = players.to_vec();
+        spec.parse::<SortSpec>().unwrap().sort(&mut players);
+        players.iter().map(|p| p.userid).collect()
+    }
+
+    fn sort_error(source: &str) -> SortError {
+        source.parse::<SortSpec>().unwrap_err()
+    }
+
+    #[test]
+    fn precedence_and_unary_minus() {
+        let p = player(1, [10, 4, 2, 3, 0, 600]);
+        assert_eq!(eval("kills + deaths * assists", &p), 10.0);
+        assert_eq!(eval("(kills + deaths) * assists", &p), 18.0);
+        assert_eq!(eval("kills - deaths - assists", &p), -1.0);
+        assert_eq!(eval("score / deaths / 5", &p), 1.0);
+        assert_eq!(eval("-kills * 2", &p), -8.0);
+        assert_eq!(eval("--kills", &p), 4.0);
+        assert_eq!(eval("kills - -deaths", &p), 6.0);
+    }
+
+    #[test]
+    fn functions_and_division_by_zero() {
+        let p = player(1, [10, 4, 0, 3, 0, 0]);
+        assert_eq!(eval("max(kills, assists, 7)", &p), 7.0);
+        assert_eq!(eval("min(kills, assists, 7)", &p), 3.0);
+        assert_eq!(eval("max(kills)", &p), 4.0);
+        assert_eq!(eval("abs(deaths - kills)", &p), 4.0);
+        assert_eq!(eval("kills / deaths", &p), 0.0);
+        assert_eq!(eval("kills / max(deaths, 1)", &p), 4.0);
+        assert_eq!(eval("kpm", &p), 0.0);
+        assert_eq!(eval("sqrt(deaths - kills)", &p), 0.0);
+        assert_eq!(sort_error("abs(kills, deaths)").message, "abs() takes exactly one argument");
+    }
+
+    #[test]
+    fn later_keys_break_ties_in_their_direction() {
+        let players = [player(1, [5, 3, 0, 0, 0, 100]), player(2, [5, 3, 0, 0, 0, 50]), player(3, [9, 1, 0, 0, 0, 10])];
+        assert_eq!(order("score", &players), [3, 1, 2]);
+        assert_eq!(order("score asc", &players), [1, 2, 3]);
+        assert_eq!(order("kills desc, time asc", &players), [2, 1, 3]);
+        assert_eq!(order("kills, time desc", &players), [1, 2, 3]);
+    }
+
+    #[test]
+    fn presets_keep_the_original_order() {
+        let players: Vec<PlayerStats> = (0..40)
+            .map(|i| player(i, [(i * 7) as i32 % 11, (i * 5) as i32 % 9, (i * 3) as i32 % 7, i as i32 % 4, 0, 60]))
+            .collect();
+        let keys: [fn(&PlayerStats) -> i32; 3] = [
+            |p| -(p.score + p.kills - p.deaths),
+            |p| -(p.assists + p.kills - p.deaths),
+            |p| -(p.score + p.assists + p.deaths),
+        ];
+        for (preset, key) in ["0", "1", "2"].into_iter().zip(keys) {
+            let mut expected = players.clone();
+            expected.sort_by_key(key);
+            assert_eq!(order(preset, &players), expected.iter().map(|p| p.userid).collect::<Vec<_>>(), "preset {}", preset);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_characters() {
+        let e = sort_error("kills + foo");
+        assert_eq!((e.position, e.len), (8, 3));
+        assert!(e.message.starts_with("unknown field 'foo'"));
+        assert_eq!(e.to_string().lines().last(), Some("          ^^^"));
+
+        let e = sort_error("kills $ 2");
+        assert_eq!((e.position, e.len, e.message.as_str()), (6, 1, "unexpected character '$'"));
+
+        let e = sort_error("(kills + 1");
+        assert_eq!((e.position, e.len, e.message.as_str()), (10, 1, "expected ')'"));
+
+        let e = sort_error("kills desc time");
+        assert_eq!((e.position, e.len, e.message.as_str()), (11, 4, "expected ',' or the end of the expression"));
+
+        let e = sort_error("avg(kills)");
+        assert_eq!((e.position, e.len), (0, 3));
+
+        let e = sort_error("kills 1.2.3");
+        assert_eq!((e.position, e.len, e.message.as_str()), (6, 5, "invalid number '1.2.3'"));
+    }
+
+    #[test]
+    fn keys_without_fields_are_rejected() {
+        let e = sort_error("3");
+        assert_eq!((e.position, e.len), (0, 1));
+        assert!(e.message.starts_with("the sort key uses no field"));
+
+        let e = sort_error("kills, -max(1, 2) asc");
+        assert_eq!((e.position, e.len), (7, 10));
+        assert!("kills, 2 * -deaths".parse::<SortSpec>().is_ok());
+    }
+}
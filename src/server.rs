@@ -0,0 +1,380 @@
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::error::ParseError;
+use crate::leaderboard::{self, Filter, parse_duration};
+use crate::output::{self, Report};
+use crate::parser::{Encoding, PlayerStats, TrafficStats, parse_stats, parse_usertype, parse_userstats_with};
+use crate::ranking::SortSpec;
+use crate::watch;
+
+const WORKERS: usize = 4;
+/// Most `/events` streams open at once; each holds a thread until the client leaves.
+const MAX_STREAMS: usize = 64;
+
+/// How often an idle event stream sends a comment so proxies keep it open.
+const KEEPALIVE: Duration = Duration::from_secs(30);
+
+/// One channel per connected `/events` client, notified after every reload.
+type Clients = Mutex<Vec<Sender<()>>>;
+
+/// What to serve and the leaderboard defaults that query parameters can override.
+pub struct ServerConfig {
+    pub userstats_path: PathBuf,
+    /// Read when it exists; without it the leaderboard has no traffic.
+    pub stats_path: PathBuf,
+    pub encoding: Encoding,
+    pub title: String,
+    pub sort: SortSpec,
+    pub limit: usize,
+    pub filter: Filter,
+    pub pretty: bool,
+    /// Whether the HTML page subscribes to `/events` and updates itself in place.
+    pub live: bool,
+    /// Whether the HTML page embeds the traffic and activity charts.
+    pub charts: bool,
+    /// Whether the HTML page embeds its stylesheet and scripts instead of using a CDN.
+    pub offline: bool,
+    /// Template source rendered instead of the built-in HTML layout.
+    pub template: Option<String>,
+    /// Also check the stats files this often, for folders where change events never arrive.
+    pub interval: Option<Duration>,
+}
+
+/// The parsed stats files, replaced as a whole whenever they change on disk.
+struct Data {
+    players: Vec<PlayerStats>,
+    traffic: Vec<TrafficStats>,
+    updated: DateTime<Local>,
+}
+
+impl Data {
+    fn load(config: &ServerConfig) -> Result<Self, ParseError> {
+        Ok(Data {
+            players: parse_userstats_with(&config.userstats_path, config.encoding)?,
+            traffic: if config.stats_path.exists() { parse_stats(&config.stats_path)? } else { Vec::new() },
+            updated: Local::now(),
+        })
+    }
+}
+
+/// A leaderboard request after applying query parameters to the defaults.
+struct Query {
+    sort: SortSpec,
+    filter: Filter,
+    limit: usize,
+    offset: usize,
+}
+
+#[derive(Serialize)]
+struct RankedPlayer<'a> {
+    rank: Option<usize>,
+    #[serde(flatten)]
+    player: &'a PlayerStats,
+}
+
+#[derive(Serialize)]
+struct PlayersPage<'a> {
+    total: usize,
+    excluded: usize,
+    offset: usize,
+    limit: usize,
+    players: Vec<RankedPlayer<'a>>,
+}
+
+#[derive(Serialize)]
+struct Update<'a> {
+    updated: DateTime<Local>,
+    #[serde(flatten)]
+    page: PlayersPage<'a>,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    title: &'a str,
+    updated: DateTime<Local>,
+    players: usize,
+    uptime: usize,
+    uploaded: u64,
+    downloaded: u64,
+}
+
+/// Serves the leaderboard over HTTP on `addr` until the process exits.
+///
+/// Both stats files are parsed once up front and again whenever `userstats.dat`
+/// changes; if a re-parse fails the previous data keeps being served.
+///
+/// Routes:
+/// - `/` renders the HTML leaderboard
+/// - `/api/players` lists ranked players (`sort`, `limit`, `offset`, `min_time`,
+///   `min_kills`, `only`, `include` and `exclude` query parameters)
+/// - `/api/players/{usertype}/{userid}` returns one player with their rank
+/// - `/api/traffic` returns all hourly traffic records
+/// - `/api/summary` returns uptime, bandwidth and player totals
+/// - `/events` streams the ranked players as Server-Sent Events: a `snapshot`
+///   event on connect and after every re-parse (same parameters as `/api/players`)
+pub fn serve(addr: &str, config: ServerConfig) -> io::Result<()> {
+    let data = Data::load(&config).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let data = Arc::new(RwLock::new(data));
+    let config = Arc::new(config);
+
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    let server = Arc::new(server);
+    let clients: Arc<Clients> = Arc::default();
+    let streams = Arc::new(AtomicUsize::new(0));
+
+    {
+        let (data, config, clients) = (Arc::clone(&data), Arc::clone(&config), Arc::clone(&clients));
+        thread::spawn(move || {
+            let triggers = watch::Triggers { events: true, interval: config.interval };
+            let result = watch::watch(
+                &[&config.userstats_path, &config.stats_path].map(|path| (path.as_path(), triggers)),
+                |_| match watch::retry(|| Data::load(&config)) {
+                    Ok(fresh) => {
+                        *data.write().unwrap() = fresh;
+                        clients.lock().unwrap().retain(|client| client.send(()).is_ok());
+                    }
+                    Err(e) => eprintln!("Error reloading stats, keeping previous data: {}", e),
+                },
+                |e| eprintln!("Watcher error: {}", e),
+            );
+            if let Err(e) = result {
+                eprintln!("Error: cannot watch '{}': {}", config.userstats_path.display(), e);
+            }
+        });
+    }
+
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let (server, data, config, clients, streams) =
+                (Arc::clone(&server), Arc::clone(&data), Arc::clone(&config), Arc::clone(&clients), Arc::clone(&streams));
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    // Event streams stay open, so they get their own thread instead of a worker.
+                    if *request.method() == Method::Get && split_url(request.url()).0 == "/events" {
+                        if streams.fetch_add(1, Ordering::SeqCst) >= MAX_STREAMS {
+                            streams.fetch_sub(1, Ordering::SeqCst);
+                            let _ = request.respond(error(503, "Too many open event streams"));
+                            continue;
+                        }
+                        let (data, config, clients, streams) =
+                            (Arc::clone(&data), Arc::clone(&config), Arc::clone(&clients), Arc::clone(&streams));
+                        thread::spawn(move || {
+                            events(request, &config, &data, &clients);
+                            streams.fetch_sub(1, Ordering::SeqCst);
+                        });
+                        continue;
+                    }
+                    let response = handle(&request, &config, &data.read().unwrap());
+                    if let Err(e) = request.respond(response) {
+                        eprintln!("Error sending response: {}", e);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+fn handle(request: &Request, config: &ServerConfig, data: &Data) -> Response<Cursor<Vec<u8>>> {
+    if *request.method() != Method::Get {
+        return error(405, "Only GET requests are supported");
+    }
+
+    let (path, params) = split_url(request.url());
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        [""] => match parse_query(config, &params) {
+            Ok(query) => html(config, data, &query, request.url()),
+            Err(e) => error(400, &e),
+        },
+        ["api", "players"] => match parse_query(config, &params) {
+            Ok(query) => players(config, data, &query),
+            Err(e) => error(400, &e),
+        },
+        ["api", "players", usertype, userid] => {
+            let key = parse_usertype(usertype).zip(userid.parse::<i64>().ok());
+            match (key, parse_query(config, &params)) {
+                (None, _) => error(400, "Expected /api/players/{usgn|steam}/{userid}"),
+                (_, Err(e)) => error(400, &e),
+                (Some(key), Ok(query)) => player(config, data, &query, key),
+            }
+        }
+        ["api", "traffic"] => json(config, &data.traffic),
+        ["api", "summary"] => {
+            let report = Report::new(&config.title, &[], &data.traffic, data.players.len());
+            json(config, &Summary {
+                title: &config.title,
+                updated: data.updated,
+                players: data.players.len(),
+                uptime: report.uptime,
+                uploaded: report.uploaded,
+                downloaded: report.downloaded,
+            })
+        }
+        _ => error(404, "Not found"),
+    }
+}
+
+/// Splits a request URL into its path and decoded query parameters.
+fn split_url(url: &str) -> (&str, HashMap<String, String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    (path, form_urlencoded::parse(query.as_bytes()).into_owned().collect())
+}
+
+/// Applies the query parameters of a leaderboard request to the configured defaults.
+fn parse_query(config: &ServerConfig, params: &HashMap<String, String>) -> Result<Query, String> {
+    let mut query = Query { sort: config.sort.clone(), filter: config.filter.clone(), limit: config.limit, offset: 0 };
+
+    for (name, value) in params {
+        let invalid = |e: &dyn std::fmt::Display| format!("Invalid '{}': {}", name, e);
+        match name.as_str() {
+            "sort" => query.sort = value.parse().map_err(|e| invalid(&e))?,
+            "limit" => query.limit = value.parse().map_err(|e| invalid(&e))?,
+            "offset" => query.offset = value.parse().map_err(|e| invalid(&e))?,
+            "min_time" => query.filter.min_time = parse_duration(value).map_err(|e| invalid(&e))?,
+            "min_kills" => query.filter.min_kills = value.parse().map_err(|e| invalid(&e))?,
+            "only" => query.filter.only = Some(parse_usertype(value).ok_or_else(|| invalid(&"expected 'usgn' or 'steam'"))?),
+            "include" => query.filter.include = Some(Regex::new(value).map_err(|e| invalid(&e))?),
+            "exclude" => query.filter.exclude = Some(Regex::new(value).map_err(|e| invalid(&e))?),
+            _ => return Err(format!("Unknown query parameter '{}'", name)),
+        }
+    }
+    Ok(query)
+}
+
+/// Ranks a copy of the current players for `query`. Returns the players and the excluded count.
+fn ranked(data: &Data, query: &Query) -> (Vec<PlayerStats>, usize) {
+    let mut players = data.players.clone();
+    let excluded = leaderboard::rank(&mut players, &query.filter, &query.sort);
+    (players, excluded)
+}
+
+/// The `offset`/`limit` window of ranked `players` requested by `query`.
+fn page<'a>(players: &'a [PlayerStats], excluded: usize, query: &Query) -> PlayersPage<'a> {
+    PlayersPage {
+        total: players.len(),
+        excluded,
+        offset: query.offset,
+        limit: query.limit,
+        players: players
+            .iter()
+            .enumerate()
+            .skip(query.offset)
+            .take(query.limit)
+            .map(|(i, player)| RankedPlayer { rank: Some(i + 1), player })
+            .collect(),
+    }
+}
+
+fn players(config: &ServerConfig, data: &Data, query: &Query) -> Response<Cursor<Vec<u8>>> {
+    let (players, excluded) = ranked(data, query);
+    json(config, &page(&players, excluded, query))
+}
+
+/// Streams a `snapshot` event now and after every reload until the client disconnects.
+fn events(request: Request, config: &ServerConfig, data: &RwLock<Data>, clients: &Clients) {
+    let query = match parse_query(config, &split_url(request.url()).1) {
+        Ok(query) => query,
+        Err(e) => {
+            let _ = request.respond(error(400, &e));
+            return;
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    clients.lock().unwrap().push(sender);
+
+    // tiny_http has no streaming responses, so write the head by hand on the raw connection.
+    let mut writer = request.into_writer();
+    let mut message = String::from(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+         Access-Control-Allow-Origin: *\r\n\r\n",
+    );
+    message.push_str(&snapshot(&data.read().unwrap(), &query));
+
+    loop {
+        if writer.write_all(message.as_bytes()).and_then(|()| writer.flush()).is_err() {
+            return;
+        }
+        message = match receiver.recv_timeout(KEEPALIVE) {
+            Ok(()) => snapshot(&data.read().unwrap(), &query),
+            Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+    }
+}
+
+/// Formats the ranked players for `query` as a `snapshot` event.
+fn snapshot(data: &Data, query: &Query) -> String {
+    let (players, excluded) = ranked(data, query);
+    let update = Update { updated: data.updated, page: page(&players, excluded, query) };
+    format!("event: snapshot\ndata: {}\n\n", serde_json::to_string(&update).unwrap_or_default())
+}
+
+fn player(config: &ServerConfig, data: &Data, query: &Query, key: (u8, i64)) -> Response<Cursor<Vec<u8>>> {
+    let Some(player) = data.players.iter().find(|p| p.key() == key) else {
+        return error(404, "Player not found");
+    };
+    let (players, _) = ranked(data, query);
+    let rank = players.iter().position(|p| p.key() == key).map(|i| i + 1);
+    json(config, &RankedPlayer { rank, player })
+}
+
+fn html(config: &ServerConfig, data: &Data, query: &Query, url: &str) -> Response<Cursor<Vec<u8>>> {
+    let (players, excluded) = ranked(data, query);
+    let users = players.len();
+    // The same window as `/players` and `/events`, so live updates replace like with like.
+    let start = query.offset.min(users);
+    let end = start.saturating_add(query.limit).min(users);
+
+    let mut report = Report::new(&config.title, &players[start..end], &data.traffic, users);
+    report.excluded = excluded;
+    report.offset = start;
+    report.charts = config.charts;
+    report.offline = config.offline;
+    report.template = config.template.as_deref();
+    // Subscribe with the page's own query so live updates keep its sort and filters.
+    let events = format!("/events{}", url.find('?').map_or("", |i| &url[i..]));
+    if config.live {
+        report.live = Some(&events);
+    }
+
+    let mut body = Vec::new();
+    match output::write_html(&mut body, &report) {
+        Ok(()) => respond(200, "text/html; charset=utf-8", body),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+fn json<T: Serialize>(config: &ServerConfig, value: &T) -> Response<Cursor<Vec<u8>>> {
+    let body = if config.pretty { serde_json::to_vec_pretty(value) } else { serde_json::to_vec(value) };
+    match body {
+        Ok(body) => respond(200, "application/json", body),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+fn error(status: u16, message: &str) -> Response<Cursor<Vec<u8>>> {
+    let body = serde_json::json!({ "error": message }).to_string().into_bytes();
+    respond(status, "application/json", body)
+}
+
+fn respond(status: u16, content_type: &str, body: Vec<u8>) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes("Content-Type", content_type).unwrap();
+    Response::from_data(body).with_status_code(status).with_header(header)
+}
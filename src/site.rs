@@ -0,0 +1,170 @@
+use chrono::{Local, NaiveDateTime};
+use minijinja::{Value, context};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use crate::output::{self, Report};
+use crate::parser::{PlayerStats, usertype_name};
+use crate::publish;
+use crate::ranking;
+use crate::template;
+
+/// Stats shown with a percentile on player pages: field name and label.
+const STATS: [(&str, &str); 9] = [
+    ("score", "Score"),
+    ("kills", "Kills"),
+    ("deaths", "Deaths"),
+    ("assists", "Assists"),
+    ("mvps", "MVPs"),
+    ("time", "Playtime"),
+    ("kd", "K/D"),
+    ("kpm", "Kills per Minute"),
+    ("spm", "Score per Minute"),
+];
+
+/// Rank and stats of every player on a past leaderboard, by player key.
+type Ranks<'a> = HashMap<(u8, i64), (usize, &'a PlayerStats)>;
+
+/// A past leaderboard, used for the timelines on player pages.
+pub struct HistoryPoint {
+    pub taken_at: NaiveDateTime,
+    /// The players ranked at that time, best first.
+    pub players: Vec<PlayerStats>,
+}
+
+#[derive(Serialize)]
+struct StatLine {
+    name: &'static str,
+    text: String,
+    /// Share of ranked players with a lower value, from 0 to 100.
+    percentile: f64,
+}
+
+#[derive(Serialize)]
+struct TimelineEntry<'a> {
+    date: String,
+    rank: usize,
+    #[serde(flatten)]
+    player: &'a PlayerStats,
+}
+
+/// Path of a player's page relative to the site root, e.g. `players/usgn/5783.html`.
+pub fn page_path(usertype: u8, userid: i64) -> String {
+    format!("players/{}/{}.html", usertype_name(usertype), userid)
+}
+
+/// Writes a static site into `dir`: `index.html` with the leaderboard of `report`,
+/// linking every row to a page for that player under `players/`.
+///
+/// `ranked` is the full leaderboard before the limit was applied and sets the
+/// percentiles on player pages. `history`, oldest first, adds a timeline of each
+/// player's rank and counters. With `keep`, that many previous versions of every
+/// page are kept as backups. Pages of players no longer on the leaderboard are
+/// deleted, see [`publish::remove`].
+pub fn write_site(
+    dir: &Path,
+    report: &Report,
+    ranked: &[PlayerStats],
+    history: &[HistoryPoint],
+    keep: Option<usize>,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let index = Report { profile_pages: true, ..*report };
+    publish::replace_keeping(&dir.join("index.html"), keep, |writer| output::write_html(writer, &index))?;
+
+    // Sorted values of every stat, to look up percentiles by binary search.
+    let distributions: Vec<Vec<f64>> = STATS
+        .iter()
+        .map(|(field, _)| {
+            let mut values: Vec<f64> = ranked.iter().map(|p| ranking::field(p, field)).collect();
+            values.sort_by(f64::total_cmp);
+            values
+        })
+        .collect();
+
+    let history: Vec<(String, Ranks)> = history
+        .iter()
+        .map(|point| {
+            let ranks = point.players.iter().enumerate().map(|(i, p)| (p.key(), (i + 1, p))).collect();
+            (point.taken_at.format("%Y-%m-%d %H:%M").to_string(), ranks)
+        })
+        .collect();
+
+    let mut env = template::environment();
+    env.add_template("player.html", template::PLAYER).map_err(io::Error::other)?;
+    let page = env.get_template("player.html").map_err(io::Error::other)?;
+    let updated = Local::now().format("%d %b %Y at %H:%M:%S").to_string();
+
+    for (i, player) in report.players.iter().enumerate() {
+        let stats: Vec<StatLine> = STATS
+            .iter()
+            .zip(&distributions)
+            .map(|((field, name), values)| {
+                let value = ranking::field(player, field);
+                let below = values.partition_point(|v| *v < value);
+                StatLine {
+                    name,
+                    text: match *field {
+                        "time" => output::format_time(player.time),
+                        "kd" | "kpm" | "spm" => format!("{:.2}", value),
+                        _ => value.to_string(),
+                    },
+                    percentile: below as f64 * 100.0 / (values.len().max(2) - 1) as f64,
+                }
+            })
+            .collect();
+
+        let timeline: Vec<TimelineEntry> = history
+            .iter()
+            .filter_map(|(date, ranks)| {
+                let (rank, player) = ranks.get(&player.key())?;
+                Some(TimelineEntry { date: date.clone(), rank: *rank, player })
+            })
+            .collect();
+
+        let html = page
+            .render(context! {
+                title => report.title,
+                updated => updated,
+                index => Value::from_safe_string("../../index.html".to_string()),
+                ranked => ranked.len(),
+                player => Value::from_serialize(template::player(report, i)),
+                stats => Value::from_serialize(&stats),
+                timeline => Value::from_serialize(&timeline),
+                assets => context! {
+                    stylesheets => Value::from_safe_string(output::stylesheets(report.offline)),
+                    scripts => Value::from_safe_string(output::scripts(report.offline, None, false)),
+                },
+            })
+            .map_err(io::Error::other)?;
+
+        let path = dir.join(page_path(player.usertype, player.userid));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        publish::replace_keeping(&path, keep, |writer| writer.write_all(output::minify(&html).as_bytes()))?;
+    }
+
+    let pages: HashSet<String> = report.players.iter().map(|p| page_path(p.usertype, p.userid)).collect();
+    for usertype in [0, 1] {
+        let folder = format!("players/{}", usertype_name(usertype));
+        let Ok(entries) = fs::read_dir(dir.join(&folder)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Backups are `<page>.html.<stamp>.bak`; without `keep`, those of gone pages go too.
+            let page = match name.find(".html") {
+                Some(end) if name.ends_with(".html") || (keep.is_none() && name.ends_with(".bak")) => &name[..end + 5],
+                _ => continue,
+            };
+            if !pages.contains(&format!("{}/{}", folder, page)) {
+                publish::remove(&entry.path().with_file_name(page), keep)?;
+            }
+        }
+    }
+
+    Ok(())
+}
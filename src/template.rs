@@ -0,0 +1,153 @@
+use chrono::Local;
+use minijinja::{Environment, Error, Value, context};
+use serde::Serialize;
+use crate::charts;
+use crate::leaderboard::Movement;
+use crate::output::{self, Report};
+use crate::parser::PlayerStats;
+use crate::ranking;
+use crate::site;
+use crate::traffic::TrafficSummary;
+
+/// The built-in HTML leaderboard layout.
+pub const HTML: &str = include_str!("../templates/leaderboard.html");
+/// The built-in Markdown leaderboard layout.
+pub const MARKDOWN: &str = include_str!("../templates/leaderboard.md");
+/// The built-in player page of a generated site.
+pub const PLAYER: &str = include_str!("../templates/player.html");
+
+#[derive(Serialize)]
+pub(crate) struct TemplatePlayer<'a> {
+    #[serde(flatten)]
+    player: &'a PlayerStats,
+    rank: usize,
+    kd: f64,
+    kpm: f64,
+    spm: f64,
+    hours: f64,
+    movement: Option<TemplateMovement>,
+}
+
+#[derive(Serialize)]
+struct TemplateMovement {
+    /// `▲3`, `▼1`, `NEW` or `–`.
+    text: String,
+    /// `up`, `down` or empty, for styling.
+    class: &'static str,
+    /// Sort key that orders new players after everyone who moved.
+    sort: i64,
+    previous_rank: Option<usize>,
+    change: Option<i64>,
+}
+
+/// Renders the template `source` for `report`.
+///
+/// `name` only picks the escaping: names ending in `.html` escape HTML in every
+/// value that is not already markup. Besides minijinja's built-ins, templates
+/// can use the `bytes`, `duration`, `fixed(digits)`, `profile_url` and `page_url` filters.
+pub fn render(name: &str, source: &str, report: &Report) -> Result<String, Error> {
+    let mut env = environment();
+    env.add_template(name, source)?;
+
+    let now = Local::now();
+    let traffic = TrafficSummary::new(report.traffic);
+    let charts = (report.charts && !report.traffic.is_empty()).then(|| {
+        context! {
+            bandwidth => Value::from_safe_string(charts::bandwidth_per_day(report.traffic)),
+            players => Value::from_safe_string(charts::players_over_time(report.traffic)),
+            heatmap => Value::from_safe_string(charts::activity_heatmap(report.traffic)),
+        }
+    });
+
+    env.get_template(name)?.render(context! {
+        title => report.title,
+        updated => now.format("%d %b %Y at %H:%M:%S").to_string(),
+        timestamp => now.to_rfc3339(),
+        summary => context! {
+            uptime => report.uptime,
+            uploaded => report.uploaded,
+            downloaded => report.downloaded,
+            total => report.uploaded + report.downloaded,
+            users => report.users,
+            excluded => report.excluded,
+        },
+        traffic => Value::from_serialize(&traffic),
+        players => Value::from_serialize(players(report)),
+        movement => report.previous_ranks.is_some(),
+        profile_pages => report.profile_pages,
+        pagination => report.pagination.map(|p| context! {
+            page => p.page,
+            count => p.links.len(),
+            previous => p.page.checked_sub(2).and_then(|i| p.links.get(i)),
+            next => p.links.get(p.page),
+            pages => p.links.iter().enumerate().map(|(i, url)| context! {
+                number => i + 1,
+                url => url,
+                current => i + 1 == p.page,
+            }).collect::<Vec<_>>(),
+        }),
+        charts => charts,
+        assets => context! {
+            stylesheets => Value::from_safe_string(output::stylesheets(report.offline)),
+            scripts => Value::from_safe_string(output::scripts(report.offline, report.live, report.profile_pages)),
+        },
+    })
+}
+
+/// An environment with the filters available to every template.
+pub(crate) fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.add_filter("bytes", output::format_bytes);
+    env.add_filter("duration", output::format_time);
+    env.add_filter("fixed", |value: f64, digits: Option<usize>| format!("{:.*}", digits.unwrap_or(2), value));
+    env.add_filter("profile_url", profile_url);
+    env.add_filter("page_url", page_url);
+    env
+}
+
+fn players<'a>(report: &Report<'a>) -> Vec<TemplatePlayer<'a>> {
+    (0..report.players.len()).map(|i| player(report, i)).collect()
+}
+
+/// The template view of the player at `index` of `report`.
+pub(crate) fn player<'a>(report: &Report<'a>, index: usize) -> TemplatePlayer<'a> {
+    let player = &report.players[index];
+    TemplatePlayer {
+        player,
+        rank: report.rank(index),
+        kd: ranking::field(player, "kd"),
+        kpm: ranking::field(player, "kpm"),
+        spm: ranking::field(player, "spm"),
+        hours: ranking::field(player, "hours"),
+        movement: report.movement(index).map(|m| TemplateMovement {
+            text: m.to_string(),
+            class: match m {
+                Movement::Up(_) | Movement::New => "up",
+                Movement::Down(_) => "down",
+                Movement::Same => "",
+            },
+            sort: m.delta().unwrap_or(report.users as i64 + 1),
+            previous_rank: report.previous_ranks.and_then(|r| r[index]),
+            change: m.delta(),
+        }),
+    }
+}
+
+/// The Steam or Unreal Software profile of a player, as markup so `/` is not escaped.
+fn profile_url(player: Value) -> Result<Value, Error> {
+    let usertype = u8::try_from(player.get_attr("usertype")?)?;
+    let userid = i64::try_from(player.get_attr("userid")?)?;
+    let url = if usertype == 1 {
+        format!("https://steamcommunity.com/profiles/{}", userid)
+    } else {
+        format!("https://unrealsoftware.de/profile.php?userid={}", userid)
+    };
+    Ok(Value::from_safe_string(url))
+}
+
+/// The player's page in a generated site, relative to the site root.
+fn page_url(player: Value) -> Result<Value, Error> {
+    let usertype = u8::try_from(player.get_attr("usertype")?)?;
+    let userid = i64::try_from(player.get_attr("userid")?)?;
+    Ok(Value::from_safe_string(site::page_path(usertype, userid)))
+}
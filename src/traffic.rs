@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use crate::parser::TrafficStats;
+
+/// Traffic and player activity aggregated over a group of hourly records.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Period {
+    /// `YYYY-MM-DD` for days, `YYYY-MM` for months and `HH:00` for hours of the day.
+    pub label: String,
+    /// Number of hourly records in the period, i.e. hours the server was up.
+    pub hours: usize,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    /// Mean concurrent players over the recorded hours.
+    pub average_players: f64,
+    pub peak_players: u8,
+}
+
+/// Aggregates of the hourly records in `stats.dat`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrafficSummary {
+    pub hours: usize,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub average_players: f64,
+    pub peak_players: u8,
+    /// Hour of the first record with `peak_players`, as `YYYY-MM-DD HH:00`.
+    pub peak_at: Option<String>,
+    /// One entry per recorded day, oldest first.
+    pub days: Vec<Period>,
+    /// One entry per recorded month, oldest first.
+    pub months: Vec<Period>,
+    /// One entry per hour of the day that has records, most average players first.
+    pub busiest_hours: Vec<Period>,
+}
+
+impl Period {
+    fn new(label: String, records: &[&TrafficStats]) -> Self {
+        let players: u64 = records.iter().map(|t| t.players as u64).sum();
+        Period {
+            label,
+            hours: records.len(),
+            uploaded: records.iter().map(|t| t.uploaded_bytes as u64).sum(),
+            downloaded: records.iter().map(|t| t.downloaded_bytes as u64).sum(),
+            average_players: if records.is_empty() { 0.0 } else { players as f64 / records.len() as f64 },
+            peak_players: records.iter().map(|t| t.players).max().unwrap_or(0),
+        }
+    }
+}
+
+impl TrafficSummary {
+    /// Aggregates `traffic` into daily, monthly and hour-of-day series.
+    pub fn new(traffic: &[TrafficStats]) -> Self {
+        let all: Vec<&TrafficStats> = traffic.iter().collect();
+        let total = Period::new(String::new(), &all);
+
+        let days = group(traffic, |t| (t.year, t.month, t.day), |(y, m, d)| format!("{:04}-{:02}-{:02}", y, m, d));
+        let months = group(traffic, |t| (t.year, t.month), |(y, m)| format!("{:04}-{:02}", y, m));
+        let mut busiest_hours = group(traffic, |t| t.hour, |h| format!("{:02}:00", h));
+        busiest_hours.sort_by(|a, b| b.average_players.total_cmp(&a.average_players));
+
+        let peak_at = traffic
+            .iter()
+            .find(|t| total.hours > 0 && t.players == total.peak_players)
+            .map(|t| format!("{:04}-{:02}-{:02} {:02}:00", t.year, t.month, t.day, t.hour));
+
+        TrafficSummary {
+            hours: total.hours,
+            uploaded: total.uploaded,
+            downloaded: total.downloaded,
+            average_players: total.average_players,
+            peak_players: total.peak_players,
+            peak_at,
+            days,
+            months,
+            busiest_hours,
+        }
+    }
+}
+
+/// Groups records by `key` in key order and labels each group with `label`.
+fn group<K: Ord + Copy>(
+    traffic: &[TrafficStats],
+    key: impl Fn(&TrafficStats) -> K,
+    label: impl Fn(K) -> String,
+) -> Vec<Period> {
+    let mut groups: BTreeMap<K, Vec<&TrafficStats>> = BTreeMap::new();
+    for t in traffic {
+        groups.entry(key(t)).or_default().push(t);
+    }
+    groups.into_iter().map(|(k, records)| Period::new(label(k), &records)).collect()
+}
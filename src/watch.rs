@@ -0,0 +1,192 @@
+use notify::event::{AccessKind, AccessMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher, recommended_watcher};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::sync::mpsc::{RecvTimeoutError, channel};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long the files must stay quiet after a change before `on_change` is called.
+/// CS2D writes its stats in several steps, each of which fires an event.
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+/// How often folders that could not be watched, or were deleted, are tried again.
+const REARM_INTERVAL: Duration = Duration::from_secs(2);
+/// Waits between attempts to read files that fail to parse, most likely because
+/// they are still being written.
+pub const RETRY_DELAYS: [Duration; 4] = [
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+];
+
+/// How [`watch`] notices that a file changed.
+#[derive(Debug, Clone, Copy)]
+pub struct Triggers {
+    /// Listen for file system events.
+    pub events: bool,
+    /// Compare the size and modification time of the file this often, for
+    /// folders where events never arrive, such as network mounts.
+    pub interval: Option<Duration>,
+}
+
+/// Blocks forever, calling `on_change` with the indices of the `paths` that
+/// changed once they have been quiet for [`DEBOUNCE`], and `on_error` when the
+/// watcher reports an error. Each path is only followed by its own [`Triggers`].
+///
+/// With events, the folders holding the files are watched rather than the files
+/// themselves, so a file that is replaced, renamed over, or deleted and recreated
+/// keeps being followed. Deleted files are left out of `on_change` until they come
+/// back. A folder that cannot be watched is reported to `on_error` and retried
+/// every few seconds; an error is only returned when no folder can be watched at
+/// the start and no path has an `interval` to fall back on.
+pub fn watch<F, E>(paths: &[(&Path, Triggers)], mut on_change: F, mut on_error: E) -> notify::Result<()>
+where
+    F: FnMut(&[usize]),
+    E: FnMut(notify::Error),
+{
+    let (tx, rx) = channel();
+    let triggers: Vec<Triggers> = paths.iter().map(|(_, triggers)| *triggers).collect();
+    // Without events the watcher is never started, and `tx` keeps the channel open.
+    let events = triggers.iter().any(|t| t.events);
+    let mut watcher = if events { Some(recommended_watcher(tx.clone())?) } else { None };
+
+    // Events carry absolute paths when the folders are watched by absolute path.
+    let paths = paths.iter().map(|(path, _)| path::absolute(path)).collect::<Result<Vec<_>, _>>()?;
+    let mut folders: Vec<(PathBuf, bool)> = Vec::new();
+    for (path, _) in paths.iter().zip(&triggers).filter(|(_, t)| t.events) {
+        let folder = path.parent().unwrap_or(path).to_path_buf();
+        if !folders.iter().any(|(f, _)| *f == folder) {
+            folders.push((folder, false));
+        }
+    }
+
+    if let Some(watcher) = &mut watcher {
+        let mut errors = arm(watcher, &mut folders);
+        if errors.len() == folders.len()
+            && triggers.iter().all(|t| t.interval.is_none())
+            && let Some(e) = errors.pop()
+        {
+            return Err(e);
+        }
+        errors.into_iter().for_each(&mut on_error);
+    }
+
+    let mut signatures: Vec<Option<Signature>> = paths.iter().map(|path| signature(path)).collect();
+    let mut last_polls = vec![Instant::now(); paths.len()];
+    let mut pending = BTreeSet::new();
+    let mut last_event = Instant::now();
+    let mut last_rearm = Instant::now();
+    loop {
+        let mut timeout = if pending.is_empty() {
+            REARM_INTERVAL
+        } else {
+            DEBOUNCE.saturating_sub(last_event.elapsed())
+        };
+        for (interval, last_poll) in triggers.iter().zip(&last_polls).filter_map(|(t, last)| Some((t.interval?, last))) {
+            timeout = timeout.min(interval.saturating_sub(last_poll.elapsed()));
+        }
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) if is_change(&event.kind) => {
+                for changed in &event.paths {
+                    for i in (0..paths.len()).filter(|i| triggers[*i].events && paths[*i] == *changed) {
+                        pending.insert(i);
+                        last_event = Instant::now();
+                    }
+                    // A deleted folder loses its watch; it is re-armed once it is back.
+                    if let Some(folder) = folders.iter_mut().find(|(f, _)| f == changed)
+                        && !folder.0.is_dir()
+                        && let Some(watcher) = &mut watcher
+                    {
+                        let _ = watcher.unwatch(&folder.0);
+                        folder.1 = false;
+                    }
+                }
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => on_error(e),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        for (i, path) in paths.iter().enumerate() {
+            if let Some(interval) = triggers[i].interval
+                && last_polls[i].elapsed() >= interval
+            {
+                last_polls[i] = Instant::now();
+                if signature(path) != signatures[i] {
+                    pending.insert(i);
+                    last_event = Instant::now();
+                }
+            }
+        }
+
+        if let Some(watcher) = &mut watcher
+            && last_rearm.elapsed() >= REARM_INTERVAL
+        {
+            last_rearm = Instant::now();
+            let unarmed: Vec<PathBuf> = folders.iter().filter(|(_, armed)| !armed).map(|(f, _)| f.clone()).collect();
+            if !unarmed.is_empty() && arm(watcher, &mut folders).len() < unarmed.len() {
+                // The files of a folder that came back may have changed meanwhile.
+                for (i, path) in paths.iter().enumerate().filter(|(i, _)| triggers[*i].events) {
+                    if path.parent().is_some_and(|folder| unarmed.iter().any(|f| f == folder)) {
+                        pending.insert(i);
+                        last_event = Instant::now();
+                    }
+                }
+            }
+        }
+
+        if !pending.is_empty() && last_event.elapsed() >= DEBOUNCE {
+            let changed: Vec<usize> = pending.iter().copied().filter(|i| paths[*i].exists()).collect();
+            for i in pending.iter().copied() {
+                signatures[i] = signature(&paths[i]);
+            }
+            pending.clear();
+            if !changed.is_empty() {
+                on_change(&changed);
+            }
+        }
+    }
+}
+
+/// Calls `load` until it succeeds, waiting [`RETRY_DELAYS`] between attempts,
+/// and returns the last error when every attempt fails.
+pub fn retry<T, E>(mut load: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    for delay in RETRY_DELAYS {
+        if let Ok(value) = load() {
+            return Ok(value);
+        }
+        thread::sleep(delay);
+    }
+    load()
+}
+
+/// Size and modification time of a file, to notice changes without events.
+type Signature = (u64, SystemTime);
+
+fn signature(path: &Path) -> Option<Signature> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Watches the folders that are not watched yet, returning why some could not be.
+fn arm(watcher: &mut RecommendedWatcher, folders: &mut [(PathBuf, bool)]) -> Vec<notify::Error> {
+    let mut errors = Vec::new();
+    for (folder, armed) in folders.iter_mut().filter(|(_, armed)| !armed) {
+        match watcher.watch(folder, RecursiveMode::NonRecursive) {
+            Ok(()) => *armed = true,
+            Err(e) => errors.push(e.add_path(folder.clone())),
+        }
+    }
+    errors
+}
+
+/// Whether an event may have changed the contents of a file: anything but reads.
+fn is_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) => false,
+        _ => true,
+    }
+}
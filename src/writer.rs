@@ -0,0 +1,67 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::parser::{PlayerStats, TrafficStats, STATS_HEADER, USERSTATS_HEADER};
+
+/// Writes `players` to the `userstats.dat` file at `path`, replacing it.
+pub fn save_userstats<P: AsRef<Path>>(path: P, players: &[PlayerStats]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_userstats(&mut writer, players)?;
+    writer.flush()
+}
+
+/// Serializes `players` in the `userstats.dat` format used by the CS2D server.
+///
+/// Names are written from [`PlayerStats::raw_name`] when it is set, so parsed
+/// records are reproduced byte for byte; otherwise the UTF-8 `name` is used.
+pub fn write_userstats<W: Write>(mut writer: W, players: &[PlayerStats]) -> io::Result<()> {
+    writeln!(writer, "{}", USERSTATS_HEADER)?;
+
+    for p in players {
+        let name = if p.raw_name.is_empty() { p.name.as_bytes() } else { &p.raw_name };
+        if name.trim_ascii().is_empty() || name.contains(&b'\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Player name {:?} cannot be stored in userstats.dat", p.name),
+            ));
+        }
+
+        writer.write_all(name)?;
+        writer.write_u8(b'\n')?;
+        writer.write_u8(p.usertype)?;
+        writer.write_i64::<LittleEndian>(p.userid)?;
+        writer.write_i32::<LittleEndian>(p.score)?;
+        writer.write_i32::<LittleEndian>(p.kills)?;
+        writer.write_i32::<LittleEndian>(p.deaths)?;
+        writer.write_i32::<LittleEndian>(p.assists)?;
+        writer.write_i32::<LittleEndian>(p.mvps)?;
+        writer.write_i32::<LittleEndian>(p.time)?;
+    }
+
+    Ok(())
+}
+
+/// Writes `traffic` to the `stats.dat` file at `path`, replacing it.
+pub fn save_stats<P: AsRef<Path>>(path: P, traffic: &[TrafficStats]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_stats(&mut writer, traffic)?;
+    writer.flush()
+}
+
+/// Serializes `traffic` in the `stats.dat` format used by the CS2D server.
+pub fn write_stats<W: Write>(mut writer: W, traffic: &[TrafficStats]) -> io::Result<()> {
+    writeln!(writer, "{}", STATS_HEADER)?;
+
+    for t in traffic {
+        writer.write_u8(t.day)?;
+        writer.write_u8(t.month)?;
+        writer.write_u16::<LittleEndian>(t.year)?;
+        writer.write_u8(t.hour)?;
+        writer.write_i64::<LittleEndian>(t.uploaded_bytes)?;
+        writer.write_i64::<LittleEndian>(t.downloaded_bytes)?;
+        writer.write_u8(t.players)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use cs2d_stats_parser::{
+    Encoding, ParseError, PlayerStats, TrafficStats, TrafficSummary, output::write_traffic_html, read_stats,
+    read_userstats, read_userstats_with, write_stats, write_userstats,
+};
+
+fn player(name: &str, usertype: u8, userid: i64) -> PlayerStats {
+    PlayerStats {
+        name: name.to_string(),
+        raw_name: Vec::new(),
+        usertype,
+        userid,
+        score: 101,
+        kills: 95,
+        deaths: 58,
+        assists: 14,
+        mvps: 37,
+        time: 3869,
+    }
+}
+
+/// Builds a `userstats.dat` image by hand, the way the CS2D server lays it out.
+fn userstats_bytes(records: &[(&[u8], u8, i64, [i32; 6])]) -> Vec<u8> {
+    let mut bytes = b"userstats steam\n".to_vec();
+    for (name, usertype, userid, fields) in records {
+        bytes.extend_from_slice(name);
+        bytes.push(b'\n');
+        bytes.push(*usertype);
+        bytes.extend_from_slice(&userid.to_le_bytes());
+        for field in fields {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[test]
+fn userstats_round_trip_is_byte_identical() {
+    let original = userstats_bytes(&[
+        (b"VN_XannerNotSus", 0, 200798, [101, 95, 58, 14, 37, 3869]),
+        ("планктон".as_bytes(), 1, 76561199154080412, [-3, 0, 7, 0, 0, 88]),
+        (b"\xcf\xe0\xed", 1, 76561199069047604, [58, 58, 41, 16, 32, 2114]),
+    ]);
+
+    let players = read_userstats_with(original.as_slice(), Encoding::Windows1251).unwrap();
+    assert_eq!(players[1].name, "планктон");
+    assert_eq!(players[2].name, "Пан");
+
+    let mut written = Vec::new();
+    write_userstats(&mut written, &players).unwrap();
+    assert_eq!(written, original);
+}
+
+#[test]
+fn userstats_written_from_names_parse_back() {
+    let players = vec![player("Zoë", 1, 76561199000000007), player("<script>", 0, 200004)];
+
+    let mut written = Vec::new();
+    write_userstats(&mut written, &players).unwrap();
+    let parsed = read_userstats(written.as_slice()).unwrap();
+
+    assert_eq!(parsed.len(), players.len());
+    for (parsed, original) in parsed.iter().zip(&players) {
+        assert_eq!(parsed.raw_name, original.name.as_bytes());
+        assert_eq!(PlayerStats { raw_name: Vec::new(), ..parsed.clone() }, *original);
+    }
+}
+
+#[test]
+fn userstats_rejects_unstorable_names() {
+    let mut written = Vec::new();
+    assert!(write_userstats(&mut written, &[player("two\nlines", 0, 1)]).is_err());
+    assert!(write_userstats(&mut written, &[player("", 0, 1)]).is_err());
+}
+
+#[test]
+fn stats_round_trip_is_byte_identical() {
+    let traffic = vec![
+        TrafficStats { day: 1, month: 4, year: 2025, hour: 0, uploaded_bytes: 123456, downloaded_bytes: 654321, players: 3 },
+        TrafficStats { day: 17, month: 4, year: 2025, hour: 23, uploaded_bytes: 0, downloaded_bytes: i64::MAX, players: 32 },
+    ];
+
+    let mut written = Vec::new();
+    write_stats(&mut written, &traffic).unwrap();
+    assert_eq!(&written[..12], b"stats alpha\n");
+    assert_eq!(written.len(), 12 + 22 * traffic.len());
+    assert_eq!(read_stats(written.as_slice()).unwrap(), traffic);
+
+    let mut rewritten = Vec::new();
+    write_stats(&mut rewritten, &read_stats(written.as_slice()).unwrap()).unwrap();
+    assert_eq!(rewritten, written);
+}
+
+/// Two records after the 16-byte header: `alpha` at bytes 16..55 and `bravo` from 55,
+/// whose usertype is at 61 and kills at 74.
+fn two_players(usertype: u8) -> Vec<u8> {
+    userstats_bytes(&[(b"alpha", 0, 1, [1, 2, 3, 4, 5, 6]), (b"bravo", usertype, 2, [1, 2, 3, 4, 5, 6])])
+}
+
+/// Two traffic records after the 12-byte header; the second starts at byte 34.
+fn two_hours() -> Vec<u8> {
+    let hour = |hour| TrafficStats { day: 1, month: 4, year: 2025, hour, uploaded_bytes: 1, downloaded_bytes: 2, players: 3 };
+    let mut written = Vec::new();
+    write_stats(&mut written, &[hour(0), hour(1)]).unwrap();
+    written
+}
+
+#[test]
+fn userstats_truncated_inside_a_name() {
+    let mut bytes = userstats_bytes(&[(b"alpha", 0, 1, [1, 2, 3, 4, 5, 6])]);
+    bytes.extend_from_slice(b"bra");
+    let e = read_userstats(bytes.as_slice()).unwrap_err();
+    assert!(matches!(e, ParseError::TruncatedRecord { offset: 55, record: 1, field: "name" }), "{:?}", e);
+}
+
+#[test]
+fn userstats_truncated_inside_a_number() {
+    let bytes = &two_players(0)[..76];
+    let e = read_userstats(bytes).unwrap_err();
+    assert!(matches!(e, ParseError::TruncatedRecord { offset: 74, record: 1, field: "kills" }), "{:?}", e);
+}
+
+#[test]
+fn userstats_rejects_an_invalid_usertype() {
+    let e = read_userstats(two_players(2).as_slice()).unwrap_err();
+    assert!(matches!(e, ParseError::InvalidField { offset: 61, record: 1, field: "usertype", value: 2 }), "{:?}", e);
+}
+
+#[test]
+fn userstats_rejects_bytes_after_an_empty_name_line() {
+    let mut bytes = userstats_bytes(&[(b"alpha", 0, 1, [1, 2, 3, 4, 5, 6])]);
+    bytes.extend_from_slice(b"\n\r\n");
+    assert_eq!(read_userstats(bytes.as_slice()).unwrap().len(), 1);
+
+    bytes.extend_from_slice(b"junk");
+    let e = read_userstats(bytes.as_slice()).unwrap_err();
+    assert!(matches!(e, ParseError::TrailingGarbage { offset: 56, record: 1, len: 6 }), "{:?}", e);
+}
+
+#[test]
+fn stats_rejects_an_invalid_month_or_hour() {
+    let mut bytes = two_hours();
+    bytes[35] = 13;
+    let e = read_stats(bytes.as_slice()).unwrap_err();
+    assert!(matches!(e, ParseError::InvalidField { offset: 35, record: 1, field: "month", value: 13 }), "{:?}", e);
+
+    let mut bytes = two_hours();
+    bytes[38] = 24;
+    let e = read_stats(bytes.as_slice()).unwrap_err();
+    assert!(matches!(e, ParseError::InvalidField { offset: 38, record: 1, field: "hour", value: 24 }), "{:?}", e);
+}
+
+#[test]
+fn stats_truncated_inside_a_record() {
+    let bytes = &two_hours()[..40];
+    let e = read_stats(bytes).unwrap_err();
+    assert!(matches!(e, ParseError::TruncatedRecord { offset: 39, record: 1, field: "uploaded_bytes" }), "{:?}", e);
+}
+
+#[test]
+fn traffic_html_escapes_the_title() {
+    let summary = TrafficSummary::new(&read_stats(two_hours().as_slice()).unwrap());
+    let mut html = Vec::new();
+    write_traffic_html(&mut html, "<script>alert(1)</script>", &summary, true).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(!html.contains("<script>alert(1)"));
+    assert!(html.contains("<title>&lt;script&gt;alert(1)&lt;/script&gt;</title>"));
+    assert!(html.contains("<h1>&lt;script&gt;alert(1)&lt;/script&gt;</h1>"));
+}